@@ -24,6 +24,12 @@ Server Options:
 	-b --bind=<addr>  Serving socket bind address. [default: [::]:4950]
 	-n --name=<name>  Set the server name. [default: RustyMedia]
 	--uuid=<uuid>  Server UUID. [default: 06289e13-a832-4d76-be0b-00151d449864]
+	--cache=<dir>  Directory for persistent caches such as probed metadata. [default: cache]
+	
+Playback Options:
+	--language=<code> ...  Preferred audio language, may be repeated in order of preference.
+		Codes are matched against the language tags of the files, usually
+		three-letter ISO 639-2 codes such as `jpn` or `eng`.
 
 Other Options:
 	-h --help  Show this help.
@@ -32,6 +38,8 @@ Other Options:
 #[derive(Deserialize)]
 struct Args {
 	flag_bind: std::net::SocketAddr,
+	flag_cache: std::path::PathBuf,
+	flag_language: Vec<String>,
 	flag_local: Vec<String>,
 	flag_name: String,
 	flag_uuid: String,
@@ -57,20 +65,31 @@ fn result_main() -> rustymedia::Result<()> {
 		.unwrap_or_else(|e| e.exit());
 	
 	let mut root = rustymedia::root::Root::new();
+	let mut watched = Vec::new();
 	
 	for mapping in args.flag_local {
 		let i = mapping.find('=').expect("No `=` found in --local mapping");
+		let name = mapping[..i].to_string();
+		let path = std::path::PathBuf::from(&mapping[i+1..]);
 		
-		root.add(rustymedia::local::Object::new_root(
-			mapping[..i].to_string(), mapping[i+1..].to_string())?);
+		root.add(rustymedia::local::Object::new_root(name.clone(), path.clone())?);
+		watched.push((name, path));
 	}
 	
 	if root.is_empty() {
 		panic!("No folders configured.");
 	}
+	
+	let library = Arc::new(rustymedia::music::Library::new());
+	root.add(rustymedia::music::View::root(library.clone()));
 	let root = Arc::new(root);
 	
+	std::fs::create_dir_all(&args.flag_cache)?;
+	let metadata = Arc::new(rustymedia::metadata::Store::open(
+		args.flag_cache.join("metadata.jsonl"))?);
+	
 	let addr = find_public_addr(args.flag_bind);
+	let cpupool = Arc::new(futures_cpupool::CpuPool::new(8));
 	
 	let handle: Arc<Mutex<Option<tokio_core::reactor::Remote>>> =
 		Arc::new(std::sync::Mutex::new(None));
@@ -80,16 +99,30 @@ fn result_main() -> rustymedia::Result<()> {
 		rustymedia::dlna::server::ServerArgs {
 			uri: format!("http://{}", addr),
 			root: root.clone(),
+			metadata: metadata.clone(),
+			thumbnails: Arc::new(rustymedia::thumbnail::Cache::new(
+				args.flag_cache.join("thumbnails"))),
+			languages: args.flag_language,
+			cpupool: cpupool.clone(),
 			remote: move || service_handle.lock().unwrap().as_ref().unwrap().clone(),
 			name: args.flag_name,
 			uuid: args.flag_uuid,
 		});
 	
+	let on_change = service.change_handler();
+	
 	let server = hyper::server::Http::new()
 		.bind(&args.flag_bind, service).unwrap();
 	
 	*handle.lock().unwrap() = Some(server.handle().remote().clone());
 	
+	let exec = rustymedia::Executors::new(server.handle().remote().clone(), cpupool);
+	rustymedia::scan::spawn(root.clone(), metadata.clone(), library.clone(), exec.clone())?;
+	rustymedia::watch::spawn(watched, move |change| {
+		on_change(change);
+		rustymedia::scan::changed(&root, &metadata, &library, &exec, change);
+	})?;
+	
 	eprintln!("Listening on http://{}/", addr);
 	rustymedia::dlna::discovery::schedule_presence_broadcasts(server.handle(), addr);
 	server.run().unwrap();
@@ -22,38 +22,77 @@ impl TranscodeCache {
 		}
 	}
 
+	/// Drop any transcodes of an item so that they are regenerated from the new source.
+	pub fn invalidate(&mut self, id: &str) {
+		if self.values.remove(id).is_some() {
+			eprintln!("Invalidated transcodes of {:?}", id);
+		}
+	}
+
+	/// Get `item` in a format that `device` can play.
+	///
+	/// Streams starting part way through are always transcoded and never cached, their transcode
+	/// stops once the response is dropped.
 	pub fn get(&mut self,
 		exec: &crate::Executors,
 		item: &Box<dyn crate::Object>,
 		format: &crate::ffmpeg::Format,
 		device: &crate::ffmpeg::Device,
+		selection: &crate::ffmpeg::Selection,
 	) -> crate::Result<std::sync::Arc<dyn crate::Media>>
 	{
-		if format.compatible_with(device) { return item.body(&exec) }
+		let transcoded_format = match target(format, device, selection) {
+			Some(transcoded_format) => transcoded_format,
+			None => return item.body(&exec),
+		};
+		if transcoded_format.start.is_some() {
+			return item.transcoded_body(exec, &format, &transcoded_format)
+		}
 
 		eprintln!("Cache size: {}", self.values.len());
-		match self.values.entry(item.id().to_owned()) {
-			lru_cache::Entry::Occupied(mut e) => {
-				for e in e.get_mut().iter_mut() {
-					eprintln!("Transcode available: {:?}", e.format);
-					if e.format.compatible_with(device) {
-						eprintln!("Transcode cache hit!");
-						return Ok(e.media.clone())
-					}
+		if let Some(entries) = self.values.get_mut(item.id()) {
+			for e in entries.iter() {
+				eprintln!("Transcode available: {:?}", e.format);
+				// The response headers describe `transcoded_format` so only an identical one will do.
+				if e.format.same_output(&transcoded_format) {
+					eprintln!("Transcode cache hit!");
+					return Ok(e.media.clone())
 				}
-				let transcoded_format = format.transcode_for(device);
-				let media = item.transcoded_body(&exec, &format, &transcoded_format)?;
-				e.get_mut().push(Entry{format: transcoded_format, media: media.clone()});
-				Ok(media)
-			}
-			lru_cache::Entry::Vacant(e) => {
-				eprintln!("Transcode cache miss!");
-				let transcoded_format = format.transcode_for(device);
-				let media = item.transcoded_body(exec, &format, &transcoded_format)?;
-				e.insert(smallvec::SmallVec::from_buf(
-					[Entry{format: transcoded_format, media: media.clone()}]));
-				Ok(media)
 			}
+			let media = item.transcoded_body(&exec, &format, &transcoded_format)?;
+			entries.push(Entry{format: transcoded_format, media: media.clone()});
+			return Ok(media)
 		}
+		
+		eprintln!("Transcode cache miss!");
+		let media = item.transcoded_body(exec, &format, &transcoded_format)?;
+		self.values.insert(item.id().to_owned(), smallvec::SmallVec::from_buf(
+			[Entry{format: transcoded_format, media: media.clone()}]));
+		Ok(media)
+	}
+}
+
+/// The format `TranscodeCache::get` serves `item` in, or `None` if the original is sent as is.
+///
+/// Selected subtitles are burned into the video if the device can't display them itself.
+pub fn target(
+	format: &crate::ffmpeg::Format,
+	device: &crate::ffmpeg::Device,
+	selection: &crate::ffmpeg::Selection,
+) -> Option<crate::ffmpeg::Format> {
+	let audio_stream = selection.audio.filter(|&i| i != format.default_audio_stream());
+	let burned_subtitles = selection.subtitles.filter(|_| !device.soft_subtitles);
+	
+	if let Some(start) = selection.start {
+		let mut transcoded_format = format.transcode_for(device, audio_stream, burned_subtitles);
+		transcoded_format.start = Some(start);
+		transcoded_format.duration = format.duration.map(|d| (d - start).max(0.0));
+		return Some(transcoded_format)
+	}
+	
+	if audio_stream.is_none() && burned_subtitles.is_none() && format.compatible_with(device) {
+		return None
 	}
+	
+	Some(format.transcode_for(device, audio_stream, burned_subtitles))
 }
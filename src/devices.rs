@@ -4,12 +4,20 @@ use regex;
 
 const ALL: Device = Device {
 	container: &[],
+	audio_container: &[],
 	video: &[],
 	audio: &[],
+	soft_subtitles: true,
 };
 
 const CHROMECAST: Device = Device {
 	container: &[ContainerFormat::MKV],
+	audio_container: &[
+		ContainerFormat::OGG,
+		ContainerFormat::MP3,
+		ContainerFormat::FLAC,
+		ContainerFormat::MKV,
+	],
 	video: &[VideoFormat::H264, VideoFormat::VP8],
 	audio: &[
 		AudioFormat::Opus,
@@ -18,10 +26,17 @@ const CHROMECAST: Device = Device {
 		AudioFormat::FLAC,
 		AudioFormat::MP3,
 	],
+	soft_subtitles: false,
 };
 
 const CHROMECAST_ULTRA: Device = Device {
 	container: &[ContainerFormat::MKV],
+	audio_container: &[
+		ContainerFormat::OGG,
+		ContainerFormat::MP3,
+		ContainerFormat::FLAC,
+		ContainerFormat::MKV,
+	],
 	video: &[VideoFormat::H264, VideoFormat::HEVC, VideoFormat::VP8],
 	audio: &[
 		// AudioFormat::AAC, // Fails to play.
@@ -30,21 +45,26 @@ const CHROMECAST_ULTRA: Device = Device {
 		AudioFormat::FLAC,
 		AudioFormat::MP3,
 	],
+	soft_subtitles: false,
 };
 
 const SAFE: Device = Device {
 	container: &[ContainerFormat::MKV],
+	audio_container: &[ContainerFormat::MP3, ContainerFormat::MKV],
 	video: &[VideoFormat::H264],
 	audio: &[
 		AudioFormat::MP3,
 		AudioFormat::AAC,
 	],
+	soft_subtitles: false,
 };
 
 const WEIRD: Device = Device {
 	container: &[ContainerFormat::MOV],
+	audio_container: &[ContainerFormat::MOV],
 	video: &[VideoFormat::HEVC],
 	audio: &[AudioFormat::MP3],
+	soft_subtitles: false,
 };
 
 const DEVICES: &[Device] = &[
@@ -55,6 +75,66 @@ const DEVICES: &[Device] = &[
 	SAFE,
 ];
 
+/// A fixed transcoding target offered to clients alongside the original file.
+#[derive(Debug)]
+pub struct Profile {
+	pub name: &'static str,
+	pub device: Device,
+}
+
+const PROFILES: &[Profile] = &[
+	Profile {
+		name: "mkv",
+		device: Device {
+			container: &[ContainerFormat::MKV],
+			audio_container: &[],
+			video: &[VideoFormat::H264],
+			audio: &[AudioFormat::AAC],
+			soft_subtitles: false,
+		},
+	},
+	Profile {
+		name: "mpegts",
+		device: Device {
+			container: &[ContainerFormat::MPEGTS],
+			audio_container: &[],
+			video: &[VideoFormat::H264],
+			audio: &[AudioFormat::AAC],
+			soft_subtitles: false,
+		},
+	},
+	Profile {
+		name: "mp3",
+		device: Device {
+			container: &[ContainerFormat::MKV],
+			audio_container: &[ContainerFormat::MP3],
+			video: &[],
+			audio: &[AudioFormat::MP3],
+			soft_subtitles: false,
+		},
+	},
+];
+
+/// The name of the profile that serves files as-is.
+pub const ORIGINAL: &str = "original";
+
+/// Look up a profile by name, `ORIGINAL` plays anything as-is.
+pub fn profile(name: &str) -> Option<&'static Device> {
+	if name == ORIGINAL { return Some(&ALL) }
+	PROFILES.iter().find(|p| p.name == name).map(|p| &p.device)
+}
+
+/// The profiles worth offering for media in `format`.
+///
+/// Video profiles are offered for video and audio profiles for audio, unless the original can
+/// already be played by the profile's targets.
+pub fn profiles_for(format: &Format) -> Vec<&'static Profile> {
+	PROFILES.iter()
+		.filter(|p| p.device.video.is_empty() != format.has_video())
+		.filter(|p| !format.compatible_with(&p.device))
+		.collect()
+}
+
 lazy_static! {
 	static ref UA_TO_DEVICE: regex::RegexSet = regex::RegexSet::new(&[
 		" aarch64\\).* CrKey/",
@@ -77,6 +157,27 @@ pub fn identify(req: &hyper::Request) -> &'static Device {
 	unreachable!()
 }
 
+/// The protocolInfo of every format combination that some known device or profile can play.
+pub fn protocol_infos() -> Vec<String> {
+	let mut infos = Vec::new();
+	let mut add = |info| if !infos.contains(&info) { infos.push(info) };
+	for device in DEVICES.iter().chain(PROFILES.iter().map(|p| &p.device)) {
+		for container in device.container {
+			for video in device.video {
+				for audio in device.audio {
+					add(protocol_info(container, Some(video), Some(audio)));
+				}
+			}
+		}
+		for container in device.audio_container {
+			for audio in device.audio.iter().filter(|a| container.carries(a)) {
+				add(protocol_info(container, None, Some(audio)));
+			}
+		}
+	}
+	infos
+}
+
 #[test]
 fn test_useragents() {
 	assert_eq!(DEVICES.len(), UA_TO_DEVICE.len());
@@ -89,3 +190,22 @@ fn test_useragents() {
 	req.headers_mut().set(hyper::header::UserAgent::new("Mozilla/5.0 (X11; Linux aarch64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/66.0.3359.120 Safari/537.36 CrKey/1.32.124602"));
 	assert_eq!(identify(&req), &CHROMECAST_ULTRA);
 }
+
+#[test]
+fn test_profiles() {
+	assert_eq!(profile(ORIGINAL), Some(&ALL));
+	assert_eq!(profile("mpegts").map(|d| d.container), Some(&[ContainerFormat::MPEGTS][..]));
+	assert_eq!(profile("avi"), None);
+}
+
+#[test]
+fn test_protocol_infos() {
+	let infos = protocol_infos();
+	assert!(infos.contains(&"http-get:*:video/x-matroska:*".to_string()));
+	assert!(infos.contains(&"http-get:*:video/quicktime:*".to_string()));
+	assert!(infos.contains(&"http-get:*:audio/mpeg:DLNA.ORG_PN=MP3".to_string()));
+	assert!(infos.contains(&"http-get:*:audio/ogg:*".to_string()));
+	assert!(infos.contains(&"http-get:*:audio/flac:*".to_string()));
+	assert!(infos.contains(&"http-get:*:video/mp2t:DLNA.ORG_PN=AVC_TS_MP_HD_AAC_MULT5".to_string()));
+	assert_eq!(infos.len(), 6);
+}
@@ -8,7 +8,13 @@ use std;
 use crate::error::ResultExt;
 
 pub mod discovery;
+pub mod events;
+pub mod filter;
+pub mod range;
+pub mod search;
+pub mod seek;
 pub mod server;
+pub mod sort;
 pub mod types;
 
 const UDN: &str = "uuid:06289e13-a832-4d76-be0b-00151d439863";
@@ -36,6 +42,21 @@ impl Request {
 			.map(|s| s.to_string())
 	}
 	
+	/// The decoded value of a query string parameter.
+	fn query(&self, key: &str) -> Option<String> {
+		self.req.query()?
+			.split('&')
+			.filter_map(|pair| {
+				let mut parts = pair.splitn(2, '=');
+				match (parts.next(), parts.next()) {
+					(Some(k), Some(v)) if k == key => Some(v),
+					_ => None,
+				}
+			})
+			.next()
+			.map(|v| percent_encoding::percent_decode(v.as_bytes()).decode_utf8_lossy().to_string())
+	}
+	
 	fn pop(&mut self) -> &str {
 		let next_chunk_start = self.path_offset;
 		let next_chunk_end = match self.path().find('/') {
@@ -0,0 +1,330 @@
+use futures;
+use futures::{Future, Stream};
+use hyper;
+use std;
+use tokio_core;
+
+/// Subscription duration used when the control point doesn't ask for one, and the maximum granted.
+pub const MAX_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1800);
+
+/// Minimum time between events for moderated state variables such as `SystemUpdateID`.
+pub const MODERATION: std::time::Duration = std::time::Duration::from_secs(2);
+
+header! { (Callback, "CALLBACK") => [String] }
+header! { (Nt, "NT") => [String] }
+header! { (Nts, "NTS") => [String] }
+header! { (Seq, "SEQ") => [u32] }
+header! { (Sid, "SID") => [String] }
+header! { (Timeout, "TIMEOUT") => [String] }
+
+#[derive(Debug)]
+struct Subscription {
+	service: &'static str,
+	callbacks: Vec<hyper::Uri>,
+	expires: std::time::Instant,
+	seq: u32,
+}
+
+/// An event ready to be sent to a single subscriber.
+#[derive(Debug)]
+pub struct Event {
+	pub sid: String,
+	pub seq: u32,
+	pub callbacks: Vec<hyper::Uri>,
+	pub body: std::sync::Arc<String>,
+}
+
+/// Active GENA subscriptions across all services.
+#[derive(Debug)]
+pub struct Subscriptions {
+	subscriptions: std::collections::HashMap<String, Subscription>,
+	random: std::collections::hash_map::RandomState,
+	next_id: u64,
+
+	/// Set once the moderation timer has been started.
+	pub moderating: bool,
+}
+
+impl Subscriptions {
+	pub fn new() -> Self {
+		Subscriptions {
+			subscriptions: std::collections::HashMap::new(),
+			random: std::collections::hash_map::RandomState::new(),
+			next_id: 0,
+			moderating: false,
+		}
+	}
+
+	fn new_sid(&mut self) -> String {
+		use std::hash::{BuildHasher, Hash, Hasher};
+
+		self.next_id += 1;
+		let mut hasher = self.random.build_hasher();
+		self.next_id.hash(&mut hasher);
+		let a = hasher.finish();
+		self.next_id.hash(&mut hasher);
+		let b = hasher.finish();
+
+		format!("uuid:{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+			a >> 32, (a >> 16) & 0xFFFF, a & 0xFFFF, b >> 48, b & 0xFFFF_FFFF_FFFF)
+	}
+
+	/// Add a subscription and return its initial event, which the caller is expected to send.
+	pub fn subscribe(&mut self,
+		service: &'static str,
+		callbacks: Vec<hyper::Uri>,
+		timeout: std::time::Duration,
+		body: String,
+	) -> Event {
+		let sid = self.new_sid();
+		eprintln!("New subscription {} to {:?} at {:?}", sid, service, callbacks);
+		self.subscriptions.insert(sid.clone(), Subscription {
+			service,
+			callbacks: callbacks.clone(),
+			expires: std::time::Instant::now() + timeout,
+			seq: 1,
+		});
+
+		Event {
+			sid,
+			seq: 0,
+			callbacks,
+			body: std::sync::Arc::new(body),
+		}
+	}
+
+	/// Extend a subscription, returning `false` if it doesn't exist or belongs to another service.
+	pub fn renew(&mut self, service: &str, sid: &str, timeout: std::time::Duration) -> bool {
+		match self.subscriptions.get_mut(sid) {
+			Some(ref mut sub) if sub.service == service => {
+				sub.expires = std::time::Instant::now() + timeout;
+				true
+			}
+			_ => false,
+		}
+	}
+
+	pub fn unsubscribe(&mut self, service: &str, sid: &str) -> bool {
+		match self.subscriptions.get(sid) {
+			Some(sub) if sub.service == service => {}
+			_ => return false,
+		}
+		eprintln!("Removed subscription {}", sid);
+		self.subscriptions.remove(sid);
+		true
+	}
+
+	pub fn expire(&mut self, now: std::time::Instant) {
+		self.subscriptions.retain(|sid, sub| {
+			let live = sub.expires > now;
+			if !live { eprintln!("Subscription {} expired", sid) }
+			live
+		});
+	}
+
+	/// Build an event for every subscriber to `service`, advancing their sequence numbers.
+	pub fn events(&mut self, service: &str, body: String) -> Vec<Event> {
+		let body = std::sync::Arc::new(body);
+		self.subscriptions.iter_mut()
+			.filter(|&(_, ref sub)| sub.service == service)
+			.map(|(sid, sub)| {
+				let seq = sub.seq;
+				// The sequence number wraps to 1 as 0 is reserved for the initial event.
+				sub.seq = sub.seq.checked_add(1).unwrap_or(1);
+				Event {
+					sid: sid.clone(),
+					seq,
+					callbacks: sub.callbacks.clone(),
+					body: body.clone(),
+				}
+			})
+			.collect()
+	}
+}
+
+/// Parse a `CALLBACK` header of the form `<url1><url2>...`.
+///
+/// Only `http` URLs are returned as they are the only ones we can deliver to.
+pub fn parse_callbacks(header: &str) -> Vec<hyper::Uri> {
+	header.split('<')
+		.filter_map(|s| s.find('>').map(|end| &s[..end]))
+		.filter_map(|s| s.trim().parse::<hyper::Uri>().ok())
+		.filter(|uri| uri.scheme() == Some("http"))
+		.collect()
+}
+
+/// Parse a `TIMEOUT` header of the form `Second-<n>` or `infinite`.
+pub fn parse_timeout(header: Option<&str>) -> std::time::Duration {
+	header
+		.and_then(|h| {
+			let h = h.trim();
+			if h.len() > 7 && h[..7].eq_ignore_ascii_case("second-") {
+				h[7..].parse().ok()
+			} else {
+				None
+			}
+		})
+		.map(std::time::Duration::from_secs)
+		.map(|timeout| std::cmp::min(timeout, MAX_TIMEOUT))
+		.unwrap_or(MAX_TIMEOUT)
+}
+
+pub fn format_timeout(timeout: std::time::Duration) -> String {
+	format!("Second-{}", timeout.as_secs())
+}
+
+/// Serialize state variables as a GENA `propertyset`.
+pub fn property_set(properties: &[(&str, String)]) -> String {
+	let mut r = String::from(
+		r#"<?xml version="1.0" encoding="utf-8"?><e:propertyset xmlns:e="urn:schemas-upnp-org:event-1-0">"#);
+	for &(name, ref value) in properties {
+		r += &format!("<e:property><{0}>{1}</{0}></e:property>", name, escape(value));
+	}
+	r += "</e:propertyset>";
+	r
+}
+
+fn escape(value: &str) -> String {
+	value
+		.replace('&', "&amp;")
+		.replace('<', "&lt;")
+		.replace('>', "&gt;")
+}
+
+/// Deliver an event, trying each callback URL in turn until one accepts it.
+pub fn send(handle: &tokio_core::reactor::Handle, event: Event)
+	-> Box<dyn Future<Item=(), Error=()>>
+{
+	let client = hyper::Client::new(handle);
+	let Event{sid, seq, callbacks, body} = event;
+	let sid2 = sid.clone();
+
+	Box::new(futures::stream::iter_ok::<_, ()>(callbacks)
+		.and_then(move |uri| {
+			let mut req = hyper::Request::new(
+				hyper::Method::Extension("NOTIFY".to_string()), uri.clone());
+			req.headers_mut().set(hyper::header::ContentType::xml());
+			req.headers_mut().set(hyper::header::ContentLength(body.len() as u64));
+			req.headers_mut().set(Nt("upnp:event".to_string()));
+			req.headers_mut().set(Nts("upnp:propchange".to_string()));
+			req.headers_mut().set(Sid(sid.clone()));
+			req.headers_mut().set(Seq(seq));
+			req.set_body(body.as_bytes().to_vec());
+
+			client.request(req).then(move |r| {
+				match r {
+					Ok(ref res) if res.status().is_success() => return Ok(true),
+					Ok(res) => eprintln!("Event to {} rejected: {}", uri, res.status()),
+					Err(e) => eprintln!("Error sending event to {}: {}", uri, e),
+				}
+				Ok(false)
+			})
+		})
+		.skip_while(|&delivered| Ok(!delivered))
+		.into_future()
+		.then(move |r| {
+			if let Ok((None, _)) = r {
+				eprintln!("Event {} for {} was not delivered.", seq, sid2);
+			}
+			Ok(())
+		}))
+}
+
+pub fn notify(remote: &tokio_core::reactor::Remote, events: Vec<Event>) {
+	if events.is_empty() { return }
+	remote.spawn(move |handle| {
+		for event in events {
+			handle.spawn(send(handle, event));
+		}
+		Ok(())
+	});
+}
+
+#[test]
+fn test_parse_headers() {
+	let callbacks = parse_callbacks("<http://10.0.0.2:80/a> <https://x/> <http://[::1]:9/b?c>");
+	assert_eq!(callbacks.len(), 2);
+	assert_eq!(callbacks[0].path(), "/a");
+	assert_eq!(callbacks[1].query(), Some("c"));
+	assert!(parse_callbacks("http://10.0.0.2/").is_empty());
+
+	assert_eq!(parse_timeout(Some("Second-300")), std::time::Duration::from_secs(300));
+	assert_eq!(parse_timeout(Some("second-99999")), MAX_TIMEOUT);
+	assert_eq!(parse_timeout(Some("infinite")), MAX_TIMEOUT);
+	assert_eq!(parse_timeout(None), MAX_TIMEOUT);
+}
+
+#[test]
+fn test_subscriptions() {
+	let mut subs = Subscriptions::new();
+	let initial = subs.subscribe("content", vec![], MAX_TIMEOUT, String::new());
+	assert_eq!(initial.seq, 0);
+	let other = subs.subscribe("connection", vec![], MAX_TIMEOUT, String::new());
+	assert_ne!(initial.sid, other.sid);
+
+	let events = subs.events("content", String::new());
+	assert_eq!(events.len(), 1);
+	assert_eq!(events[0].sid, initial.sid);
+	assert_eq!(events[0].seq, 1);
+	assert_eq!(subs.events("content", String::new())[0].seq, 2);
+
+	assert!(!subs.renew("connection", &initial.sid, MAX_TIMEOUT));
+	assert!(subs.renew("content", &initial.sid, MAX_TIMEOUT));
+	assert!(subs.unsubscribe("content", &initial.sid));
+	assert!(!subs.unsubscribe("content", &initial.sid));
+
+	subs.expire(std::time::Instant::now() + MAX_TIMEOUT);
+	assert!(subs.events("connection", String::new()).is_empty());
+}
+
+#[test]
+fn test_send() {
+	use std::io::{BufRead, Read, Write};
+
+	let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+	let addr = listener.local_addr().unwrap();
+	let server = std::thread::spawn(move || {
+		let (stream, _) = listener.accept().unwrap();
+		let mut reader = std::io::BufReader::new(stream);
+		let mut head = String::new();
+		let mut len = 0;
+		loop {
+			let mut line = String::new();
+			reader.read_line(&mut line).unwrap();
+			if line.to_lowercase().starts_with("content-length:") {
+				len = line[15..].trim().parse().unwrap();
+			}
+			if line == "\r\n" { break }
+			head += &line;
+		}
+		let mut body = vec![0; len];
+		reader.read_exact(&mut body).unwrap();
+		reader.get_mut().write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").unwrap();
+		(head, String::from_utf8(body).unwrap())
+	});
+
+	let mut core = tokio_core::reactor::Core::new().unwrap();
+	let event = Event {
+		sid: "uuid:1234".to_string(),
+		seq: 3,
+		callbacks: vec![
+			format!("http://{}/callback", addr).parse().unwrap(),
+		],
+		body: std::sync::Arc::new(property_set(&[
+			("SystemUpdateID", "5".to_string()),
+			("ContainerUpdateIDs", "a&b,5".to_string()),
+		])),
+	};
+	core.run(send(&core.handle(), event)).unwrap();
+
+	let (head, body) = server.join().unwrap();
+	let head = head.to_lowercase();
+	assert!(head.starts_with("notify /callback http/1.1\r\n"), "{}", head);
+	assert!(head.contains("\r\nsid: uuid:1234\r\n"), "{}", head);
+	assert!(head.contains("\r\nseq: 3\r\n"), "{}", head);
+	assert!(head.contains("\r\nnt: upnp:event\r\n"), "{}", head);
+	assert!(head.contains("\r\nnts: upnp:propchange\r\n"), "{}", head);
+	assert!(body.contains(
+		"<e:property><SystemUpdateID>5</SystemUpdateID></e:property>\
+		<e:property><ContainerUpdateIDs>a&amp;b,5</ContainerUpdateIDs></e:property>"), "{}", body);
+}
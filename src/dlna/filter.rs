@@ -0,0 +1,44 @@
+/// A parsed Browse or Search `Filter`.
+///
+/// Required properties are always emitted, the filter only controls optional ones.
+#[derive(Debug,PartialEq)]
+pub enum Filter {
+	All,
+	Only(Vec<String>),
+}
+
+impl Filter {
+	pub fn parse(input: &str) -> Self {
+		let properties = input.split(',')
+			.map(|s| s.trim())
+			.filter(|s| !s.is_empty())
+			.collect::<Vec<_>>();
+
+		if properties.contains(&"*") {
+			return Filter::All
+		}
+
+		Filter::Only(properties.into_iter().map(|s| s.to_string()).collect())
+	}
+
+	pub fn includes(&self, property: &str) -> bool {
+		match *self {
+			Filter::All => true,
+			Filter::Only(ref properties) => properties.iter().any(|p| p == property),
+		}
+	}
+}
+
+#[test]
+fn test_filter() {
+	assert_eq!(Filter::parse("*"), Filter::All);
+	assert_eq!(Filter::parse("dc:title,*"), Filter::All);
+	assert_eq!(Filter::parse(""), Filter::Only(vec![]));
+
+	let filter = Filter::parse("dc:date, res@size,upnp:albumArtURI");
+	assert!(filter.includes("dc:date"));
+	assert!(filter.includes("res@size"));
+	assert!(filter.includes("upnp:albumArtURI"));
+	assert!(!filter.includes("res@duration"));
+	assert!(Filter::All.includes("res@duration"));
+}
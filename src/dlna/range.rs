@@ -0,0 +1,204 @@
+use hyper;
+use std;
+
+/// How to answer a request for a resource that may ask for part of it.
+#[derive(Debug,PartialEq)]
+pub enum Ranges {
+	/// Send the whole resource.
+	Full,
+	/// Send these inclusive byte ranges, sorted and not overlapping.
+	Partial(Vec<(u64, u64)>),
+	/// None of the requested ranges exist.
+	Unsatisfiable,
+}
+
+/// The `ETag` and `Last-Modified` validators for a version of a file.
+pub fn validators(version: &crate::metadata::Key)
+	-> (hyper::header::EntityTag, hyper::header::HttpDate)
+{
+	use std::hash::{Hash, Hasher};
+
+	let mut hasher = std::collections::hash_map::DefaultHasher::new();
+	version.hash(&mut hasher);
+	let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(version.mtime.0);
+
+	(
+		hyper::header::EntityTag::strong(format!("{:016x}", hasher.finish())),
+		modified.into(),
+	)
+}
+
+/// Resolve the ranges requested in `headers` against a resource of `len` bytes.
+///
+/// `validators` are those of the resource if it is stable, `If-Range` never matches otherwise.
+pub fn resolve(
+	headers: &hyper::Headers,
+	len: u64,
+	validators: Option<&(hyper::header::EntityTag, hyper::header::HttpDate)>,
+) -> Ranges {
+	let specs = match headers.get::<hyper::header::Range>() {
+		Some(&hyper::header::Range::Bytes(ref specs)) => specs,
+		_ => return Ranges::Full,
+	};
+
+	if let Some(condition) = headers.get::<hyper::header::IfRange>() {
+		let matches = match (condition, validators) {
+			(&hyper::header::IfRange::EntityTag(ref tag), Some(&(ref etag, _))) =>
+				etag.strong_eq(tag),
+			(&hyper::header::IfRange::Date(ref date), Some(&(_, ref modified))) =>
+				date == modified,
+			(_, None) => false,
+		};
+		if !matches { return Ranges::Full }
+	}
+
+	let mut ranges = specs.iter()
+		.filter_map(|spec| match *spec {
+			hyper::header::ByteRangeSpec::FromTo(start, end) if start < len && start <= end =>
+				Some((start, end.min(len - 1))),
+			hyper::header::ByteRangeSpec::AllFrom(start) if start < len =>
+				Some((start, len - 1)),
+			hyper::header::ByteRangeSpec::Last(n) if n > 0 && len > 0 =>
+				Some((len - n.min(len), len - 1)),
+			_ => None,
+		})
+		.collect::<Vec<_>>();
+
+	if ranges.is_empty() { return Ranges::Unsatisfiable }
+
+	// Coalesce overlapping and adjacent ranges.
+	ranges.sort();
+	let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+	for (start, end) in ranges {
+		match merged.last_mut() {
+			Some(last) if start <= last.1 + 1 => last.1 = last.1.max(end),
+			_ => merged.push((start, end)),
+		}
+	}
+	Ranges::Partial(merged)
+}
+
+/// Whether the client's copy, identified by the conditional `headers`, is still current.
+///
+/// `If-Modified-Since` is only considered without an `If-None-Match`.
+pub fn not_modified(
+	headers: &hyper::Headers,
+	validators: &(hyper::header::EntityTag, hyper::header::HttpDate),
+) -> bool {
+	if let Some(condition) = headers.get::<hyper::header::IfNoneMatch>() {
+		return match *condition {
+			hyper::header::IfNoneMatch::Any => true,
+			hyper::header::IfNoneMatch::Items(ref tags) =>
+				tags.iter().any(|tag| tag.weak_eq(&validators.0)),
+		}
+	}
+	
+	match headers.get::<hyper::header::IfModifiedSince>() {
+		Some(&hyper::header::IfModifiedSince(since)) => validators.1 <= since,
+		None => false,
+	}
+}
+
+/// The headers preceding a part of a `multipart/byteranges` body.
+pub fn part_header(
+	boundary: &str,
+	content_type: &hyper::header::ContentType,
+	(start, end): (u64, u64),
+	len: Option<u64>,
+) -> String {
+	format!("\r\n--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+		boundary, content_type, start, end,
+		len.map(|l| l.to_string()).unwrap_or_else(|| "*".to_string()))
+}
+
+/// The end of a `multipart/byteranges` body.
+pub fn multipart_trailer(boundary: &str) -> String {
+	format!("\r\n--{}--\r\n", boundary)
+}
+
+#[test]
+fn test_resolve() {
+	let resolve_header = |range: &str, len| {
+		let mut headers = hyper::Headers::new();
+		headers.set_raw("Range", range.to_string());
+		resolve(&headers, len, None)
+	};
+
+	assert_eq!(resolve(&hyper::Headers::new(), 100, None), Ranges::Full);
+	assert_eq!(resolve_header("bytes=10-19", 100), Ranges::Partial(vec![(10, 19)]));
+	assert_eq!(resolve_header("bytes=90-200", 100), Ranges::Partial(vec![(90, 99)]));
+	assert_eq!(resolve_header("bytes=50-", 100), Ranges::Partial(vec![(50, 99)]));
+	assert_eq!(resolve_header("bytes=-10", 100), Ranges::Partial(vec![(90, 99)]));
+	assert_eq!(resolve_header("bytes=-500", 100), Ranges::Partial(vec![(0, 99)]));
+	assert_eq!(resolve_header("bytes=100-", 100), Ranges::Unsatisfiable);
+	assert_eq!(resolve_header("bytes=-0", 100), Ranges::Unsatisfiable);
+	assert_eq!(resolve_header("bytes=0-", 0), Ranges::Unsatisfiable);
+	assert_eq!(resolve_header("bytes=60-69,0-9,5-14,15-19", 100),
+		Ranges::Partial(vec![(0, 19), (60, 69)]));
+	assert_eq!(resolve_header("bytes=0-9,200-", 100), Ranges::Partial(vec![(0, 9)]));
+}
+
+#[test]
+fn test_if_range() {
+	let key = crate::metadata::Key {
+		path: "/a.mkv".into(),
+		mtime: (1_500_000_000, 5),
+		size: 100,
+	};
+	let validators = validators(&key);
+	let mut headers = hyper::Headers::new();
+	headers.set_raw("Range", "bytes=0-9");
+
+	headers.set(hyper::header::IfRange::EntityTag(validators.0.clone()));
+	assert_eq!(resolve(&headers, 100, Some(&validators)), Ranges::Partial(vec![(0, 9)]));
+	assert_eq!(resolve(&headers, 100, None), Ranges::Full);
+
+	headers.set(hyper::header::IfRange::EntityTag(hyper::header::EntityTag::weak(
+		validators.0.tag().to_string())));
+	assert_eq!(resolve(&headers, 100, Some(&validators)), Ranges::Full);
+
+	headers.set(hyper::header::IfRange::Date(validators.1));
+	assert_eq!(resolve(&headers, 100, Some(&validators)), Ranges::Partial(vec![(0, 9)]));
+
+	let earlier = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_400_000_000);
+	headers.set(hyper::header::IfRange::Date(earlier.into()));
+	assert_eq!(resolve(&headers, 100, Some(&validators)), Ranges::Full);
+}
+
+#[test]
+fn test_not_modified() {
+	let key = crate::metadata::Key {
+		path: "/a.mkv".into(),
+		mtime: (1_500_000_000, 5),
+		size: 100,
+	};
+	let validators = validators(&key);
+	let earlier = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_400_000_000);
+	let mut headers = hyper::Headers::new();
+	assert!(!not_modified(&headers, &validators));
+	
+	headers.set(hyper::header::IfModifiedSince(validators.1));
+	assert!(not_modified(&headers, &validators));
+	headers.set(hyper::header::IfModifiedSince(earlier.into()));
+	assert!(!not_modified(&headers, &validators));
+	
+	headers.set(hyper::header::IfNoneMatch::Items(vec![
+		hyper::header::EntityTag::weak(validators.0.tag().to_string()),
+	]));
+	assert!(not_modified(&headers, &validators));
+	headers.set(hyper::header::IfNoneMatch::Items(vec![
+		hyper::header::EntityTag::strong("other".to_string()),
+	]));
+	headers.set(hyper::header::IfModifiedSince(validators.1));
+	assert!(!not_modified(&headers, &validators));
+	headers.set(hyper::header::IfNoneMatch::Any);
+	assert!(not_modified(&headers, &validators));
+}
+
+#[test]
+fn test_part_header() {
+	assert_eq!(
+		part_header("B", &hyper::header::ContentType::octet_stream(), (0, 9), Some(100)),
+		"\r\n--B\r\nContent-Type: application/octet-stream\r\nContent-Range: bytes 0-9/100\r\n\r\n");
+	assert_eq!(multipart_trailer("B"), "\r\n--B--\r\n");
+}
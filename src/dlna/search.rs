@@ -0,0 +1,285 @@
+use std;
+
+/// A parsed UPnP ContentDirectory `SearchCriteria` expression.
+#[derive(Debug,PartialEq)]
+pub enum Criteria {
+	All,
+	And(Box<Criteria>, Box<Criteria>),
+	Or(Box<Criteria>, Box<Criteria>),
+	Compare(String, Op, String),
+	Exists(String, bool),
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Op {
+	Eq,
+	Ne,
+	Lt,
+	Le,
+	Gt,
+	Ge,
+	Contains,
+	DoesNotContain,
+	DerivedFrom,
+}
+
+#[derive(Debug,PartialEq)]
+enum Token<'a> {
+	Open,
+	Close,
+	Op(&'a str),
+	Quoted(String),
+	Word(&'a str),
+}
+
+fn tokenize(input: &str) -> crate::Result<Vec<Token>> {
+	let mut tokens = Vec::new();
+	let mut rest = input;
+
+	loop {
+		rest = rest.trim_start();
+		let c = match rest.chars().next() {
+			Some(c) => c,
+			None => return Ok(tokens),
+		};
+
+		let len = match c {
+			'(' => { tokens.push(Token::Open); 1 }
+			')' => { tokens.push(Token::Close); 1 }
+			'=' => { tokens.push(Token::Op("=")); 1 }
+			'!' | '<' | '>' => {
+				let len = if rest[1..].starts_with('=') { 2 } else { 1 };
+				if &rest[..len] == "!" {
+					return Err(crate::ErrorKind::Invalid(
+						format!("Expected `!=` in search criteria {:?}", input)).into())
+				}
+				tokens.push(Token::Op(&rest[..len]));
+				len
+			}
+			'"' => {
+				let mut value = String::new();
+				let mut chars = rest.char_indices().skip(1);
+				let end = loop {
+					match chars.next() {
+						Some((_, '\\')) => match chars.next() {
+							Some((_, c)) => value.push(c),
+							None => break None,
+						},
+						Some((i, '"')) => break Some(i),
+						Some((_, c)) => value.push(c),
+						None => break None,
+					}
+				};
+				let end = end.ok_or_else(|| crate::ErrorKind::Invalid(
+					format!("Unterminated string in search criteria {:?}", input)))?;
+				tokens.push(Token::Quoted(value));
+				end + 1
+			}
+			_ => {
+				let len = rest
+					.find(|c: char| c.is_whitespace() || "()=!<>\"".contains(c))
+					.unwrap_or(rest.len());
+				tokens.push(Token::Word(&rest[..len]));
+				len
+			}
+		};
+		rest = &rest[len..];
+	}
+}
+
+struct Parser<'a> {
+	input: &'a str,
+	tokens: std::iter::Peekable<std::vec::IntoIter<Token<'a>>>,
+}
+
+impl<'a> Parser<'a> {
+	fn error<T>(&self, msg: &str) -> crate::Result<T> {
+		Err(crate::ErrorKind::Invalid(
+			format!("{} in search criteria {:?}", msg, self.input)).into())
+	}
+
+	fn next_is_word(&mut self, word: &str) -> bool {
+		match self.tokens.peek() {
+			Some(&Token::Word(w)) if w.eq_ignore_ascii_case(word) => {
+				self.tokens.next();
+				true
+			}
+			_ => false,
+		}
+	}
+
+	fn parse_or(&mut self) -> crate::Result<Criteria> {
+		let mut criteria = self.parse_and()?;
+		while self.next_is_word("or") {
+			criteria = Criteria::Or(Box::new(criteria), Box::new(self.parse_and()?));
+		}
+		Ok(criteria)
+	}
+
+	fn parse_and(&mut self) -> crate::Result<Criteria> {
+		let mut criteria = self.parse_primary()?;
+		while self.next_is_word("and") {
+			criteria = Criteria::And(Box::new(criteria), Box::new(self.parse_primary()?));
+		}
+		Ok(criteria)
+	}
+
+	fn parse_primary(&mut self) -> crate::Result<Criteria> {
+		let property = match self.tokens.next() {
+			Some(Token::Open) => {
+				let criteria = self.parse_or()?;
+				return match self.tokens.next() {
+					Some(Token::Close) => Ok(criteria),
+					_ => self.error("Expected `)`"),
+				}
+			}
+			Some(Token::Word(property)) => property.to_string(),
+			_ => return self.error("Expected property"),
+		};
+
+		let op = match self.tokens.next() {
+			Some(Token::Op("=")) => Op::Eq,
+			Some(Token::Op("!=")) => Op::Ne,
+			Some(Token::Op("<")) => Op::Lt,
+			Some(Token::Op("<=")) => Op::Le,
+			Some(Token::Op(">")) => Op::Gt,
+			Some(Token::Op(">=")) => Op::Ge,
+			Some(Token::Word(w)) if w.eq_ignore_ascii_case("contains") => Op::Contains,
+			Some(Token::Word(w)) if w.eq_ignore_ascii_case("doesNotContain") => Op::DoesNotContain,
+			Some(Token::Word(w)) if w.eq_ignore_ascii_case("derivedfrom") => Op::DerivedFrom,
+			Some(Token::Word(w)) if w.eq_ignore_ascii_case("exists") => {
+				return match self.tokens.next() {
+					Some(Token::Word(b)) if b.eq_ignore_ascii_case("true") =>
+						Ok(Criteria::Exists(property, true)),
+					Some(Token::Word(b)) if b.eq_ignore_ascii_case("false") =>
+						Ok(Criteria::Exists(property, false)),
+					_ => self.error("Expected `true` or `false`"),
+				}
+			}
+			_ => return self.error("Expected operator"),
+		};
+
+		match self.tokens.next() {
+			Some(Token::Quoted(value)) => Ok(Criteria::Compare(property, op, value)),
+			_ => self.error("Expected quoted value"),
+		}
+	}
+}
+
+impl Criteria {
+	pub fn parse(input: &str) -> crate::Result<Criteria> {
+		if input.trim() == "*" {
+			return Ok(Criteria::All)
+		}
+
+		let mut parser = Parser {
+			input,
+			tokens: tokenize(input)?.into_iter().peekable(),
+		};
+		let criteria = parser.parse_or()?;
+		if parser.tokens.next().is_some() {
+			return parser.error("Unexpected trailing input")
+		}
+		Ok(criteria)
+	}
+
+	pub fn matches(&self, object: &dyn crate::Object) -> bool {
+		match *self {
+			Criteria::All => true,
+			Criteria::And(ref l, ref r) => l.matches(object) && r.matches(object),
+			Criteria::Or(ref l, ref r) => l.matches(object) || r.matches(object),
+			Criteria::Exists(ref property, exists) =>
+				property_value(object, property).is_some() == exists,
+			Criteria::Compare(ref property, op, ref expected) => {
+				match property_value(object, property) {
+					Some(actual) => compare(&actual, op, expected),
+					None => false,
+				}
+			}
+		}
+	}
+}
+
+fn property_value(object: &dyn crate::Object, property: &str) -> Option<String> {
+	match property {
+		"@id" => Some(object.id().to_string()),
+		"@parentID" => Some(object.parent_id().to_string()),
+		"dc:title" => Some(object.title()),
+		"upnp:class" => Some(object.dlna_class().to_string()),
+		_ => None,
+	}
+}
+
+fn compare(actual: &str, op: Op, expected: &str) -> bool {
+	let actual = actual.to_lowercase();
+	let expected = expected.to_lowercase();
+
+	let ordering = || match (actual.parse::<i64>(), expected.parse::<i64>()) {
+		(Ok(a), Ok(e)) => a.cmp(&e),
+		_ => actual.cmp(&expected),
+	};
+
+	match op {
+		Op::Eq => actual == expected,
+		Op::Ne => actual != expected,
+		Op::Lt => ordering() == std::cmp::Ordering::Less,
+		Op::Le => ordering() != std::cmp::Ordering::Greater,
+		Op::Gt => ordering() == std::cmp::Ordering::Greater,
+		Op::Ge => ordering() != std::cmp::Ordering::Less,
+		Op::Contains => actual.contains(&expected),
+		Op::DoesNotContain => !actual.contains(&expected),
+		Op::DerivedFrom =>
+			actual == expected || actual.starts_with(&format!("{}.", expected)),
+	}
+}
+
+#[test]
+fn test_parse() {
+	use self::Criteria::*;
+
+	let cmp = |p: &str, op, v: &str| Compare(p.to_string(), op, v.to_string());
+
+	assert_eq!(Criteria::parse("*").unwrap(), All);
+	assert_eq!(
+		Criteria::parse(r#"dc:title contains "alien""#).unwrap(),
+		cmp("dc:title", Op::Contains, "alien"));
+	assert_eq!(
+		Criteria::parse(r#"upnp:class derivedfrom "object.item.videoItem""#).unwrap(),
+		cmp("upnp:class", Op::DerivedFrom, "object.item.videoItem"));
+	assert_eq!(
+		Criteria::parse(r#"@id!="a\"b" and dc:date exists false"#).unwrap(),
+		And(
+			Box::new(cmp("@id", Op::Ne, "a\"b")),
+			Box::new(Exists("dc:date".to_string(), false))));
+	assert_eq!(
+		Criteria::parse(r#"(@id = "1" or @id = "2") and @parentID = "0""#).unwrap(),
+		And(
+			Box::new(Or(
+				Box::new(cmp("@id", Op::Eq, "1")),
+				Box::new(cmp("@id", Op::Eq, "2")))),
+			Box::new(cmp("@parentID", Op::Eq, "0"))));
+	assert_eq!(
+		Criteria::parse(r#"@id = "1" or @id = "2" and @id = "3""#).unwrap(),
+		Or(
+			Box::new(cmp("@id", Op::Eq, "1")),
+			Box::new(And(
+				Box::new(cmp("@id", Op::Eq, "2")),
+				Box::new(cmp("@id", Op::Eq, "3"))))));
+
+	assert!(Criteria::parse("").is_err());
+	assert!(Criteria::parse(r#"dc:title contains"#).is_err());
+	assert!(Criteria::parse(r#"dc:title contains "open"#).is_err());
+	assert!(Criteria::parse(r#"(dc:title = "a""#).is_err());
+	assert!(Criteria::parse(r#"dc:title = "a" dc:title"#).is_err());
+}
+
+#[test]
+fn test_compare() {
+	assert!(compare("Alien Resurrection", Op::Contains, "alien"));
+	assert!(!compare("Aliens", Op::DoesNotContain, "ALIEN"));
+	assert!(compare("object.item.videoItem", Op::DerivedFrom, "object.item"));
+	assert!(!compare("object.itemfoo", Op::DerivedFrom, "object.item"));
+	assert!(compare("10", Op::Gt, "9"));
+	assert!(compare("b", Op::Ge, "a"));
+	assert!(compare("9", Op::Le, "9"));
+}
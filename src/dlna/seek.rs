@@ -0,0 +1,61 @@
+// Requests a time range of a stream, `npt=<start>-[<end>]`.
+header! { (TimeSeekRange, "TimeSeekRange.dlna.org") => [String] }
+// The time range that can be requested, `<mode> npt=<start>-<end>`.
+header! { (AvailableSeekRange, "availableSeekRange.dlna.org") => [String] }
+
+/// Parse a normal play time, either seconds or `H:MM:SS` with optional fractional seconds.
+pub fn parse_time(input: &str) -> Option<f64> {
+	let input = input.trim();
+	let secs = if input.contains(':') {
+		let mut parts = input.splitn(3, ':');
+		let hours = parts.next()?.parse::<u64>().ok()?;
+		let minutes = parts.next()?.parse::<u64>().ok()?;
+		let seconds = parts.next()?.parse::<f64>().ok()?;
+		if minutes >= 60 || seconds >= 60.0 { return None }
+		(hours * 3600 + minutes * 60) as f64 + seconds
+	} else {
+		input.parse::<f64>().ok()?
+	};
+
+	if secs.is_finite() && secs >= 0.0 { Some(secs) } else { None }
+}
+
+/// Parse a `TimeSeekRange.dlna.org` value into a start and optional end.
+pub fn parse_range(input: &str) -> Option<(f64, Option<f64>)> {
+	let input = input.trim();
+	if !input.starts_with("npt=") { return None }
+
+	let mut parts = input[4..].splitn(2, '-');
+	let start = parse_time(parts.next()?)?;
+	let end = match parts.next()?.trim() {
+		"" => None,
+		end => Some(parse_time(end)?),
+	};
+
+	match end {
+		Some(end) if end < start => None,
+		end => Some((start, end)),
+	}
+}
+
+/// Format the response to a seek of a stream lasting `duration` seconds.
+///
+/// Streams always run to the end so any requested end is not echoed.
+pub fn format_range(start: f64, duration: f64) -> String {
+	format!("npt={:.3}-{:.3}/{:.3}", start, duration, duration)
+}
+
+#[test]
+fn test_parse_range() {
+	assert_eq!(parse_time("12.5"), Some(12.5));
+	assert_eq!(parse_time("1:02:03.5"), Some(3723.5));
+	assert_eq!(parse_time("0:61:00"), None);
+	assert_eq!(parse_time("-1"), None);
+
+	assert_eq!(parse_range("npt=10-"), Some((10.0, None)));
+	assert_eq!(parse_range("npt=0:00:10.000-0:01:00"), Some((10.0, Some(60.0))));
+	assert_eq!(parse_range("npt=20-10"), None);
+	assert_eq!(parse_range("bytes=0-"), None);
+
+	assert_eq!(format_range(10.0, 60.5), "npt=10.000-60.500/60.500");
+}
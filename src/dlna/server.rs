@@ -16,12 +16,51 @@ use crate::error::{ResultExt};
 const CONNECTION_XML: &str = include_str!("connection.xml");
 const CONTENT_XML: &str = include_str!("content.xml");
 
+/// `res` properties that require probing the media.
+const RES_FORMAT_PROPERTIES: &[&str] = &[
+	"res",
+	"res@bitrate",
+	"res@duration",
+	"res@nrAudioChannels",
+	"res@resolution",
+	"res@sampleFrequency",
+];
+
+/// Music properties that require probing the tags of audio files.
+const TAG_PROPERTIES: &[&str] = &[
+	"dc:creator",
+	"dc:date",
+	"upnp:album",
+	"upnp:artist",
+	"upnp:genre",
+	"upnp:originalTrackNumber",
+];
+
+/// Fraction of the duration at which video poster frames are taken.
+const POSTER_POSITION: f64 = 0.1;
+
+/// Images used as the cover of the directory containing them, in order of preference.
+const FOLDER_IMAGES: &[&str] = &["folder.jpg", "Folder.jpg", "poster.jpg", "Poster.jpg"];
+
+const SEARCH_CAPABILITIES: &str = "@id,@parentID,dc:title,upnp:class";
+const SORT_CAPABILITIES: &str = "dc:title,dc:date,upnp:class,res@size";
+
 header! { (Soapaction, "Soapaction") => [String] }
+header! { (GetCaptionInfoSec, "getCaptionInfo.sec") => [String] }
+header! { (CaptionInfoSec, "CaptionInfo.sec") => [String] }
+header! { (TransferMode, "transferMode.dlna.org") => [String] }
+header! { (GetContentFeatures, "getcontentFeatures.dlna.org") => [String] }
+header! { (ContentFeatures, "contentFeatures.dlna.org") => [String] }
 
 pub struct ServerArgs<F> {
 	pub uri: String,
 	pub remote: F,
 	pub root: std::sync::Arc<crate::root::Root>,
+	pub metadata: std::sync::Arc<crate::metadata::Store>,
+	pub thumbnails: std::sync::Arc<crate::thumbnail::Cache>,
+	/// Preferred audio languages, most preferred first.
+	pub languages: Vec<String>,
+	pub cpupool: std::sync::Arc<futures_cpupool::CpuPool>,
 	pub name: String,
 	pub uuid: String,
 }
@@ -29,6 +68,11 @@ pub struct ServerArgs<F> {
 #[derive(Debug)]
 struct Shared {
 	transcode_cache: std::sync::Mutex<crate::cache::TranscodeCache>,
+	metadata: std::sync::Arc<crate::metadata::Store>,
+	thumbnails: std::sync::Arc<crate::thumbnail::Cache>,
+	languages: Vec<String>,
+	update_ids: std::sync::Mutex<crate::updates::UpdateIds>,
+	subscriptions: std::sync::Mutex<dlna::events::Subscriptions>,
 }
 
 pub struct ServerFactory<F> {
@@ -49,13 +93,69 @@ impl<F> ServerFactory<F> {
 			root: args.root,
 			shared: std::sync::Arc::new(Shared {
 				transcode_cache: std::sync::Mutex::new(crate::cache::TranscodeCache::new()),
+				metadata: args.metadata,
+				thumbnails: args.thumbnails,
+				languages: args.languages,
+				update_ids: std::sync::Mutex::new(crate::updates::UpdateIds::new()),
+				subscriptions: std::sync::Mutex::new(dlna::events::Subscriptions::new()),
 			}),
 			root_xml: format!(include_str!("root.xml"),
 				name=args.name,
 				uuid=args.uuid
 			).into(),
 			
-			cpupool: std::sync::Arc::new(futures_cpupool::CpuPool::new(8)),
+			cpupool: args.cpupool,
+		}
+	}
+	
+	/// A callback that applies filesystem changes reported by `watch::spawn`.
+	pub fn change_handler(&self) -> impl Fn(&crate::watch::Change) + Send + 'static {
+		let shared = self.shared.clone();
+		move |change| shared.changed(change)
+	}
+}
+
+impl Shared {
+	fn changed(&self, change: &crate::watch::Change) {
+		self.update_ids.lock().unwrap().changed(&change.container);
+		self.transcode_cache.lock().unwrap().invalidate(&change.object);
+		self.metadata.remove(&change.path);
+	}
+	
+	/// The current value of every evented state variable of a service.
+	fn event_state(&self, service: &str) -> String {
+		match service {
+			"connection" => dlna::events::property_set(&[
+				("SourceProtocolInfo", crate::devices::protocol_infos().join(",")),
+				("SinkProtocolInfo", String::new()),
+				("CurrentConnectionIDs", "0".to_string()),
+			]),
+			"content" => dlna::events::property_set(&[
+				("SystemUpdateID", self.update_ids.lock().unwrap().system().to_string()),
+				("ContainerUpdateIDs", String::new()),
+				("TransferIDs", String::new()),
+			]),
+			other => unreachable!("Unknown service {:?}", other),
+		}
+	}
+	
+	/// Notify ContentDirectory subscribers of containers changed since the last call.
+	fn publish_changes(&self, handle: &tokio_core::reactor::Handle) {
+		let (system, changes) = {
+			let mut update_ids = self.update_ids.lock().unwrap();
+			(update_ids.system(), update_ids.take_changes())
+		};
+		
+		let mut subscriptions = self.subscriptions.lock().unwrap();
+		subscriptions.expire(std::time::Instant::now());
+		if changes.is_empty() { return }
+		
+		let body = dlna::events::property_set(&[
+			("SystemUpdateID", system.to_string()),
+			("ContainerUpdateIDs", format_container_update_ids(&changes)),
+		]);
+		for event in subscriptions.events("content", body) {
+			handle.spawn(dlna::events::send(handle, event));
 		}
 	}
 }
@@ -91,30 +191,145 @@ impl Server {
 			root: factory.root.clone(),
 			shared: factory.shared.clone(),
 			root_xml: factory.root_xml.clone(),
-			exec: crate::Executors {
-				handle: (factory.remote)().handle().unwrap(),
-				cpupool: factory.cpupool.clone(),
-			},
+			exec: crate::Executors::new((factory.remote)(), factory.cpupool.clone()),
+		}
+	}
+	
+	fn format(&self, item: &dyn crate::Object) -> crate::Future<crate::ffmpeg::Format> {
+		crate::metadata::format(&self.shared.metadata, item, &self.exec)
+	}
+	
+	/// Get the format of an item on the cpupool so that probing doesn't block the reactor.
+	fn spawn_format(&self, item: &dyn crate::Object) -> crate::Future<crate::ffmpeg::Format> {
+		Box::new(self.exec.cpupool.spawn(self.format(item)))
+	}
+	
+	/// Respond to `req` with `media`, honouring conditional and range headers.
+	fn respond_media(&self,
+		req: &hyper::Request,
+		mut response: hyper::Response,
+		media: &dyn crate::Media,
+	) -> crate::Result<hyper::Response> {
+		response.headers_mut().set(hyper::header::AcceptRanges(vec![
+			hyper::header::RangeUnit::Bytes,
+		]));
+		
+		let size = media.size();
+		// Ranges past the transcoded point wait for the data if the final size is known.
+		let limit = size.total.unwrap_or(size.available);
+		let validators = media.version().map(|version| dlna::range::validators(&version));
+		
+		if let Some(ref validators) = validators {
+			response.headers_mut().set(hyper::header::ETag(validators.0.clone()));
+			response.headers_mut().set(hyper::header::LastModified(validators.1));
+			if dlna::range::not_modified(req.headers(), validators) {
+				response.set_status(hyper::StatusCode::NotModified);
+				return Ok(response)
+			}
+		}
+		
+		let content = match dlna::range::resolve(req.headers(), limit, validators.as_ref()) {
+			dlna::range::Ranges::Full => {
+				if let Some(size) = size.total {
+					response.headers_mut().set(hyper::header::ContentLength(size));
+				}
+				media.read_all()
+			}
+			dlna::range::Ranges::Partial(ref ranges) if ranges.len() == 1 => {
+				let (start, end) = ranges[0];
+				response.set_status(hyper::StatusCode::PartialContent);
+				response.headers_mut().set(hyper::header::ContentRange(
+					hyper::header::ContentRangeSpec::Bytes{
+						range: Some((start, end)),
+						instance_length: size.total,
+					}));
+				response.headers_mut().set(hyper::header::ContentLength(end+1-start));
+				media.read_range(start, end+1)
+			}
+			dlna::range::Ranges::Partial(ranges) => {
+				let content_type = response.headers().get::<hyper::header::ContentType>().cloned()
+					.unwrap_or_else(hyper::header::ContentType::octet_stream);
+				let boundary = multipart_boundary();
+				
+				let mut len = 0;
+				let mut parts: Vec<crate::ByteStream> = Vec::new();
+				for range in ranges {
+					let header = dlna::range::part_header(&boundary, &content_type, range, size.total);
+					len += header.len() as u64 + range.1 + 1 - range.0;
+					parts.push(Box::new(futures::stream::once(Ok(header.into_bytes()))));
+					parts.push(media.read_range(range.0, range.1 + 1));
+				}
+				let trailer = dlna::range::multipart_trailer(&boundary);
+				len += trailer.len() as u64;
+				parts.push(Box::new(futures::stream::once(Ok(trailer.into_bytes()))));
+				
+				response.set_status(hyper::StatusCode::PartialContent);
+				response.headers_mut().set_raw("Content-Type",
+					format!("multipart/byteranges; boundary={}", boundary));
+				response.headers_mut().set(hyper::header::ContentLength(len));
+				Box::new(futures::stream::iter_ok::<_, crate::Error>(parts).flatten())
+			}
+			dlna::range::Ranges::Unsatisfiable => {
+				response.set_status(hyper::StatusCode::RangeNotSatisfiable);
+				response.headers_mut().set(hyper::header::ContentRange(
+					hyper::header::ContentRangeSpec::Bytes{
+						range: None,
+						instance_length: Some(limit),
+					}));
+				response.headers_mut().set(hyper::header::ContentLength(0));
+				return Ok(response)
+			}
+		};
+		
+		// HEAD gets the same headers without reading anything.
+		if *req.method() == hyper::Method::Head {
+			return Ok(response)
 		}
+		
+		let content = content
+			.map(|c| Ok(c.into()))
+			.map_err(|e| e.into());
+		
+		let (sender, body) = hyper::Body::pair();
+		self.exec.spawn(
+			sender.send_all(content)
+				.map(|_| ())
+				.then(|r| r.chain_err(|| "Error sending body.")))?;
+		
+		eprintln!("Response: {:?}", response);
+		response.set_body(body);
+		Ok(response)
+	}
+	
+	/// Respond with a cached or freshly generated thumbnail.
+	fn thumbnail(&self,
+		item: Box<dyn crate::Object>,
+		profile: crate::thumbnail::Profile,
+		seek: Option<f64>,
+	) -> BoxedResponse {
+		let thumbnails = self.shared.thumbnails.clone();
+		let exec = self.exec.clone();
+		Box::new(self.exec.cpupool.spawn_fn(move || {
+			let data = thumbnails.get(&*item, profile, seek, &exec)?;
+			Ok(hyper::Response::new()
+				.with_header(hyper::header::ContentType::jpeg())
+				.with_header(hyper::header::ContentLength(data.len() as u64))
+				.with_body(data))
+		}))
 	}
 }
 
 impl ServerRef {
 	fn call_root(&self, mut req: dlna::Request) -> BoxedResponse {
 		match req.pop() {
-			"root.xml" => {
-				if *req.req.method() != hyper::Method::Get {
-					return call_method_not_allowed(req)
-				}
-				
-				respond_ok(
-					hyper::Response::new()
-						.with_status(hyper::StatusCode::Ok)
-						.with_body(self.0.root_xml.clone()))
-			}
+			"root.xml" => respond_xml(req, self.0.root_xml.clone()),
 			"connection" => self.call_connection(req),
 			"content" => self.call_content(req),
+			"events" => self.call_events(req),
 			"files" => self.call_files(req),
+			"image" => self.call_image(req),
+			"subtitles" => self.call_subtitles(req),
+			"thumb" => self.call_thumb(req),
 			"video" => self.call_video(req),
 			_ => call_not_found(req),
 		}
@@ -122,9 +337,8 @@ impl ServerRef {
 	
 	fn call_connection(&self, mut req: dlna::Request) -> BoxedResponse {
 		match req.pop() {
-			"desc.xml" => {
-				respond_ok(hyper::Response::new().with_body(CONNECTION_XML))
-			}
+			"control" => self.call_connection_soap(req),
+			"desc.xml" => respond_xml(req, CONNECTION_XML),
 			_ => call_not_found(req),
 		}
 	}
@@ -132,27 +346,201 @@ impl ServerRef {
 	fn call_content(&self, mut req: dlna::Request) -> BoxedResponse {
 		match req.pop() {
 			"control" => self.call_content_soap(req),
-			"desc.xml" => respond_ok(hyper::Response::new().with_body(CONTENT_XML)),
+			"desc.xml" => respond_xml(req, CONTENT_XML),
 			_ => call_not_found(req),
 		}
 	}
 	
-	fn call_content_soap(&self, req: dlna::Request) -> BoxedResponse {
-		let action = match req.req.headers().get::<Soapaction>() {
-			Some(action) => {
-				let action = action.trim_matches('"');
-				if !action.starts_with("urn:schemas-upnp-org:service:ContentDirectory:1#") {
-					return respond_soap_fault(&format!("Unknown action namespace: {:?}", action))
-				}
-				&action[48..]
+	fn call_events(&self, mut req: dlna::Request) -> BoxedResponse {
+		let service = match req.pop() {
+			"connection" => "connection",
+			"content" => "content",
+			_ => return call_not_found(req),
+		};
+		
+		match req.req.method().as_ref() {
+			"SUBSCRIBE" => self.call_subscribe(service, req),
+			"UNSUBSCRIBE" => self.call_unsubscribe(service, req),
+			_ => call_method_not_allowed(req),
+		}
+	}
+	
+	fn call_subscribe(&self, service: &'static str, req: dlna::Request) -> BoxedResponse {
+		let headers = req.req.headers();
+		let timeout = dlna::events::parse_timeout(
+			headers.get::<dlna::events::Timeout>().map(|t| &t[..]));
+		let respond = |sid: String| respond_ok(
+			hyper::Response::new()
+				.with_header(dlna::events::Sid(sid))
+				.with_header(dlna::events::Timeout(dlna::events::format_timeout(timeout)))
+				.with_header(hyper::header::ContentLength(0)));
+		
+		if let Some(sid) = headers.get::<dlna::events::Sid>() {
+			if headers.has::<dlna::events::Callback>() || headers.has::<dlna::events::Nt>() {
+				return respond_status(hyper::StatusCode::BadRequest)
+			}
+			if !self.0.shared.subscriptions.lock().unwrap().renew(service, sid, timeout) {
+				return respond_status(hyper::StatusCode::PreconditionFailed)
+			}
+			return respond(sid.to_string())
+		}
+		
+		match headers.get::<dlna::events::Nt>() {
+			Some(nt) if nt.0 == "upnp:event" => {}
+			_ => return respond_status(hyper::StatusCode::PreconditionFailed),
+		}
+		let callbacks = headers.get::<dlna::events::Callback>()
+			.map(|c| dlna::events::parse_callbacks(c))
+			.unwrap_or_default();
+		if callbacks.is_empty() {
+			return respond_status(hyper::StatusCode::PreconditionFailed)
+		}
+		
+		let shared = &self.0.shared;
+		let event = {
+			let mut subscriptions = shared.subscriptions.lock().unwrap();
+			if !subscriptions.moderating {
+				subscriptions.moderating = true;
+				let shared = shared.clone();
+				self.0.exec.remote.spawn(move |handle| {
+					let handle = handle.clone();
+					tokio_core::reactor::Interval::new(dlna::events::MODERATION, &handle).unwrap()
+						.for_each(move |_| {
+							shared.publish_changes(&handle);
+							Ok(())
+						})
+						.map_err(|e| { eprintln!("Error in event moderation timer: {:?}", e); })
+				});
 			}
-			None => return respond_soap_fault("No Soapaction header."),
-		}.to_string(); // TODO: Fix this last lifetime fix.
+			subscriptions.subscribe(service, callbacks, timeout, shared.event_state(service))
+		};
+		
+		let sid = event.sid.clone();
+		dlna::events::notify(&self.0.exec.remote, vec![event]);
+		respond(sid)
+	}
+	
+	fn call_unsubscribe(&self, service: &'static str, req: dlna::Request) -> BoxedResponse {
+		let headers = req.req.headers();
+		let sid = match headers.get::<dlna::events::Sid>() {
+			Some(sid) => sid,
+			None => return respond_status(hyper::StatusCode::PreconditionFailed),
+		};
+		if headers.has::<dlna::events::Callback>() || headers.has::<dlna::events::Nt>() {
+			return respond_status(hyper::StatusCode::BadRequest)
+		}
+		
+		if !self.0.shared.subscriptions.lock().unwrap().unsubscribe(service, sid) {
+			return respond_status(hyper::StatusCode::PreconditionFailed)
+		}
+		respond_ok(hyper::Response::new().with_header(hyper::header::ContentLength(0)))
+	}
+	
+	fn call_connection_soap(&self, req: dlna::Request) -> BoxedResponse {
+		let action = match soap_action(&req, "ConnectionManager") {
+			Ok(action) => action,
+			Err(response) => return response,
+		};
+		
+		match &action[..] {
+			"GetCurrentConnectionIDs" => {
+				Box::new(futures::future::result(respond_soap(
+					dlna::types::BodyGetCurrentConnectionIdsResponse {
+						get_current_connection_ids_response:
+							dlna::types::GetCurrentConnectionIdsResponse {
+								_start_body: crate::xml::Body(()),
+								connection_ids: "0",
+							},
+					})))
+			}
+			"GetCurrentConnectionInfo" => {
+				Box::new(req.to_xml().and_then(|x: dlna::types::Envelope<
+					dlna::types::BodyGetCurrentConnectionInfo>|
+				{
+					let id = x.body.get_current_connection_info.connection_id;
+					if id != 0 {
+						return soap_fault(&format!("Invalid connection reference {}", id))
+					}
+					
+					respond_soap(dlna::types::BodyGetCurrentConnectionInfoResponse {
+						get_current_connection_info_response:
+							dlna::types::GetCurrentConnectionInfoResponse {
+								_start_body: crate::xml::Body(()),
+								rcs_id: -1,
+								av_transport_id: -1,
+								protocol_info: "",
+								peer_connection_manager: "",
+								peer_connection_id: -1,
+								direction: "Output",
+								status: "OK",
+							},
+					})
+				}))
+			}
+			"GetProtocolInfo" => {
+				Box::new(futures::future::result(respond_soap(
+					dlna::types::BodyGetProtocolInfoResponse {
+						get_protocol_info_response: dlna::types::GetProtocolInfoResponse {
+							_start_body: crate::xml::Body(()),
+							source: crate::devices::protocol_infos().join(","),
+							sink: "",
+						},
+					})))
+			}
+			other => respond_soap_fault(&format!("Unknown action {:?}", other)),
+		}
+	}
+	
+	fn call_content_soap(&self, req: dlna::Request) -> BoxedResponse {
+		let action = match soap_action(&req, "ContentDirectory") {
+			Ok(action) => action,
+			Err(response) => return response,
+		};
 		
 		match &action[..] {
 			"Browse" => {
 				let this = self.clone();
-				Box::new(req.to_xml().and_then(move |x| this.call_dlna_browse(x.body)))
+				Box::new(req.to_xml().and_then(move |x| {
+					// Listings may need to probe files, keep that off the reactor.
+					this.0.exec.cpupool.clone().spawn_fn(move || this.call_dlna_browse(x.body))
+				}))
+			}
+			"GetSearchCapabilities" => {
+				Box::new(futures::future::result(respond_soap(
+					dlna::types::BodyGetSearchCapabilitiesResponse {
+						get_search_capabilities_response:
+							dlna::types::GetSearchCapabilitiesResponse {
+								_start_body: crate::xml::Body(()),
+								search_caps: SEARCH_CAPABILITIES,
+							},
+					})))
+			}
+			"GetSortCapabilities" => {
+				Box::new(futures::future::result(respond_soap(
+					dlna::types::BodyGetSortCapabilitiesResponse {
+						get_sort_capabilities_response:
+							dlna::types::GetSortCapabilitiesResponse {
+								_start_body: crate::xml::Body(()),
+								sort_caps: SORT_CAPABILITIES,
+							},
+					})))
+			}
+			"GetSystemUpdateID" => {
+				let id = self.0.shared.update_ids.lock().unwrap().system();
+				Box::new(futures::future::result(respond_soap(
+					dlna::types::BodyGetSystemUpdateIdResponse {
+						get_system_update_id_response:
+							dlna::types::GetSystemUpdateIdResponse {
+								_start_body: crate::xml::Body(()),
+								id,
+							},
+					})))
+			}
+			"Search" => {
+				let this = self.clone();
+				Box::new(req.to_xml().and_then(move |x| {
+					this.0.exec.cpupool.clone().spawn_fn(move || this.call_dlna_search(x.body))
+				}))
 			}
 			other => respond_soap_fault(&format!("Unknown action {:?}", other)),
 		}
@@ -168,31 +556,110 @@ impl ServerRef {
 			Err(e) => return respond_err(e),
 		};
 		
-		let server = self.0.clone();
+		let content_type = match item.file_type() {
+			crate::Type::Image => hyper::header::ContentType(image_mime(item.id()).parse().unwrap()),
+			crate::Type::Subtitles => match subtitle_format(&*item) {
+				Some(format) => hyper::header::ContentType(format.mime().parse().unwrap()),
+				None => hyper::header::ContentType::octet_stream(),
+			},
+			_ => hyper::header::ContentType::octet_stream(),
+		};
 		
-		let r = item.body(&server.exec)
-			.and_then(move |media| {
-				let mut response = hyper::Response::new()
-					.with_header(hyper::header::ContentType::octet_stream());
-
-				let content = media.read_all()
-					.map(|c| Ok(c.into()))
-					.map_err(|e| e.into());
-				
-				let (sender, body) = hyper::Body::pair();
-				server.exec.spawn(
-					sender.send_all(content)
-						.map(|_| ())
-						.then(|r| r.chain_err(|| "Error sending body.")))?;
-				
-				eprintln!("Response: {:?}", response);
-				response.set_body(body);
-				Ok(response)
+		let r = item.body(&self.0.exec)
+			.and_then(|media| {
+				let response = hyper::Response::new()
+					.with_header(content_type);
+				self.0.respond_media(&req.req, response, &*media)
 			});
 		
 		Box::new(futures::future::result(r))
 	}
+
+	fn call_image(&self, mut req: dlna::Request) -> BoxedResponse {
+		let profile = crate::thumbnail::Profile::parse(req.pop());
+		let profile = match profile {
+			Some(profile) => profile,
+			None => return call_not_found(req),
+		};
+		let path = match req.decoded_path() {
+			Ok(p) => p,
+			Err(e) => return respond_err(e),
+		};
+		let item = match self.0.root.lookup(&path) {
+			Ok(item) => item,
+			Err(e) => return respond_err(e),
+		};
+
+		self.0.thumbnail(item, profile, None)
+	}
 	
+	fn call_subtitles(&self, mut req: dlna::Request) -> BoxedResponse {
+		let format = crate::ffmpeg::SubtitleFormat::from_extension(req.pop());
+		let format = match format {
+			Some(format) => format,
+			None => return call_not_found(req),
+		};
+		let path = match req.decoded_path() {
+			Ok(p) => p,
+			Err(e) => return respond_err(e),
+		};
+		let item = match self.0.root.lookup(&path) {
+			Ok(item) => item,
+			Err(e) => return respond_err(e),
+		};
+		if item.file_type() != crate::Type::Subtitles {
+			return call_not_found(req)
+		}
+		
+		let exec = self.0.exec.clone();
+		Box::new(self.0.exec.cpupool.spawn_fn(move || {
+			let data = crate::ffmpeg::convert_subtitles(item.ffmpeg_input(&exec)?, format, &exec)
+				.chain_err(|| format!("Error converting {:?} to {:?}", item.id(), format))?;
+			Ok(hyper::Response::new()
+				.with_header(hyper::header::ContentType(format.mime().parse().unwrap()))
+				.with_header(hyper::header::ContentLength(data.len() as u64))
+				.with_body(data))
+		}))
+	}
+	
+	/// Find the subtitle files that belong to `item`, sorted by id.
+	fn sidecar_subtitles(&self, item: &dyn crate::Object) -> Vec<Box<dyn crate::Object>> {
+		let siblings = self.0.root.lookup(item.parent_id())
+			.and_then(|parent| parent.children());
+		let siblings = match siblings {
+			Ok(siblings) => siblings,
+			Err(e) => {
+				eprintln!("Error listing {:?}: {}", item.parent_id(), e.display_chain());
+				return Vec::new()
+			}
+		};
+		
+		let mut subtitles = siblings.into_iter()
+			.filter(|entry| entry.file_type() == crate::Type::Subtitles)
+			.filter(|entry| named_after(entry.id(), item.prefix()))
+			.collect::<Vec<_>>();
+		subtitles.sort_by(|a, b| a.id().cmp(b.id()));
+		subtitles
+	}
+	
+	fn call_thumb(&self, req: dlna::Request) -> BoxedResponse {
+		let path = match req.decoded_path() {
+			Ok(p) => p,
+			Err(e) => return respond_err(e),
+		};
+		let item = match self.0.root.lookup(&path) {
+			Ok(item) => item,
+			Err(e) => return respond_err(e),
+		};
+		
+		let server = self.0.clone();
+		Box::new(self.0.spawn_format(&*item)
+			.and_then(move |format| {
+				let seek = format.duration.map(|d| d * POSTER_POSITION);
+				server.thumbnail(item, crate::thumbnail::Profile::JpegTn, seek)
+			}))
+	}
+
 	fn call_video(&self, req: dlna::Request) -> BoxedResponse {
 		let path = match req.decoded_path() {
 			Ok(p) => p,
@@ -204,181 +671,738 @@ impl ServerRef {
 		};
 		
 		let server = self.0.clone();
-		let server2 = self.0.clone();
 
-		let device = crate::devices::identify(&req.req);
+		let profile = req.query("profile");
+		let device = match profile {
+			None => crate::devices::identify(&req.req),
+			Some(ref name) => match crate::devices::profile(name) {
+				Some(device) => device,
+				None => return call_not_found(req),
+			},
+		};
+		let original = profile.as_ref().map_or(false, |p| p == crate::devices::ORIGINAL);
+		let audio = match req.query("audio").map(|s| s.parse::<usize>()) {
+			None => None,
+			Some(Ok(i)) => Some(i),
+			Some(Err(_)) => return respond_status(hyper::StatusCode::BadRequest),
+		};
+		let subtitles = match req.query("subtitles").map(|s| s.parse::<usize>()) {
+			None => None,
+			Some(Ok(i)) => Some(i),
+			Some(Err(_)) => return respond_status(hyper::StatusCode::BadRequest),
+		};
+		// The stream always runs to the end so a requested end is ignored.
+		let seek = match req.req.headers().get::<dlna::seek::TimeSeekRange>() {
+			None => None,
+			Some(range) => match dlna::seek::parse_range(range) {
+				Some((start, _)) => Some(start),
+				None => return respond_status(hyper::StatusCode::BadRequest),
+			},
+		};
+		let start = match req.query("t").map(|t| dlna::seek::parse_time(&t)) {
+			None => seek,
+			Some(Some(t)) => Some(t),
+			Some(None) => return respond_status(hyper::StatusCode::BadRequest),
+		};
+		// Background transfers are allowed by our flags, Interactive is only for images.
+		let transfer_mode = match req.req.headers().get::<TransferMode>().map(|m| m.trim()) {
+			None | Some("Streaming") => "Streaming",
+			Some("Background") => "Background",
+			Some(_) => return respond_status(hyper::StatusCode::NotAcceptable),
+		};
+		let content_features = match req.req.headers().get::<GetContentFeatures>().map(|v| v.trim()) {
+			None => false,
+			Some("1") => true,
+			Some(_) => return respond_status(hyper::StatusCode::BadRequest),
+		};
+		let head = *req.req.method() == hyper::Method::Head;
+		let caption = match req.req.headers().get::<GetCaptionInfoSec>().map(|v| v.trim()) {
+			Some("1") => self.sidecar_subtitles(&*item).first()
+				.map(|subtitles| subtitle_url(&self.0.uri, &**subtitles, crate::ffmpeg::SubtitleFormat::SRT)),
+			_ => None,
+		};
 		
-		let r = item.format(&server.exec)
+		let r = server.spawn_format(&*item)
 			.and_then(move |format| {
-				let mut cache = server.shared.transcode_cache.lock().unwrap();
-				cache.get(&server.exec, &item, &format, &device)
-			})
-			.and_then(move |media| {
-				let mut response = hyper::Response::new()
-					.with_header(hyper::header::AcceptRanges(vec![
-						hyper::header::RangeUnit::Bytes,
-					]))
-					.with_header(hyper::header::ContentType::octet_stream());
-				
-				let size = media.size();
+				let selection = crate::ffmpeg::Selection {
+					audio: match audio {
+						Some(audio) => Some(audio),
+						None if original => None,
+						None => Some(format.preferred_audio_stream(&server.shared.languages)),
+					},
+					subtitles,
+					start,
+				};
+				if let Some(status) = selection_error(&format, device, &selection) {
+					return Ok(hyper::Response::new().with_status(status))
+				}
+				let target = crate::cache::target(&format, &device, &selection);
+				// Answer HEAD from the estimated size rather than starting a transcode.
+				let media = if head && target.is_some() {
+					None
+				} else {
+					Some(server.shared.transcode_cache.lock().unwrap()
+						.get(&server.exec, &item, &format, &device, &selection)?)
+				};
 				
-				let range = req.req.headers().get::<hyper::header::Range>()
-					.and_then(|range| match *range {
-						hyper::header::Range::Bytes(ref spec) => Some(spec),
-						_ => None,
-					})
-					.and_then(|spec| spec.first())
-					.and_then(|range| match *range {
-						hyper::header::ByteRangeSpec::FromTo(start, end) => {
-							if start < size.available {
-								Some((start, end.min(size.available-1)))
-							} else {
-								None
-							}
-						},
-						hyper::header::ByteRangeSpec::AllFrom(start) => {
-							if start < size.available {
-								Some((start, size.available-1))
-							} else {
-								None
-							}
-						},
-						hyper::header::ByteRangeSpec::Last(_) => {
-							None
-						}
-					});
+				let served = target.as_ref().unwrap_or(&format);
+				let mut response = hyper::Response::new()
+					.with_header(hyper::header::ContentType(served.mime().parse().unwrap()))
+					.with_header(TransferMode(transfer_mode.to_string()));
+				if content_features {
+					response.headers_mut().set(
+						ContentFeatures(served.content_features(target.is_some())));
+				}
+				if let Some(caption) = caption {
+					response.headers_mut().set(CaptionInfoSec(caption));
+				}
+				// Only answer a TimeSeekRange header, `?t=` requests don't expect one.
+				if let (Some(start), Some(duration)) = (start.filter(|_| seek.is_some()), format.duration) {
+					response.headers_mut().set(dlna::seek::TimeSeekRange(
+						dlna::seek::format_range(start, duration)));
+					response.headers_mut().set(dlna::seek::AvailableSeekRange(
+						format!("1 npt=0.000-{:.3}", duration)));
+				}
 				
-				let content = match range {
-					Some((start, end)) => {
-						response.set_status(hyper::StatusCode::PartialContent);
-						response.headers_mut().set(hyper::header::ContentRange(
-							hyper::header::ContentRangeSpec::Bytes{
-								range: Some((start, end)),
-								instance_length: size.total,
-							}));
-						response.headers_mut().set(hyper::header::ContentLength(end+1-start));
-						media.read_range(start, end+1)
-					},
+				match media {
+					Some(media) => server.respond_media(&req.req, response, &*media),
 					None => {
-						if let Some(size) = size.total {
+						response.headers_mut().set(hyper::header::AcceptRanges(vec![
+							hyper::header::RangeUnit::Bytes,
+						]));
+						if let Some(size) = target.and_then(|t| t.estimated_size()) {
 							response.headers_mut().set(hyper::header::ContentLength(size));
 						}
-						media.read_all() // No range.
+						Ok(response)
 					}
-				};
-				
-				let content = content
-					.map(|c| Ok(c.into()))
-					.map_err(|e| e.into());
-				
-				let (sender, body) = hyper::Body::pair();
-				server2.exec.spawn(
-					sender.send_all(content)
-						.map(|_| ())
-						.then(|r| r.chain_err(|| "Error sending body.")))?;
-				
-				eprintln!("Response: {:?}", response);
-				response.set_body(body);
-				Ok(response)
+				}
 			});
 		
 		Box::new(r)
 	}
 	
 	fn call_dlna_browse(self, body: dlna::types::Body) -> crate::Result<hyper::Response> {
-		let object = self.0.root.lookup(&body.browse.object_id)?;
+		match &body.browse.browse_flag[..] {
+			"BrowseDirectChildren" => self.call_dlna_browse_children(body.browse),
+			"BrowseMetadata" => self.call_dlna_browse_metadata(body.browse),
+			other => Err(crate::ErrorKind::Invalid(
+				format!("Unknown BrowseFlag {:?}", other)).into()),
+		}
+	}
+	
+	fn call_dlna_browse_children(self, browse: dlna::types::Browse)
+		-> crate::Result<hyper::Response>
+	{
+		let object = self.0.root.lookup(&browse.object_id)?;
+		let children = object.children()?;
+		let update_id = self.0.shared.update_ids.lock().unwrap()
+			.observe(object.id(), crate::updates::fingerprint(&children));
+		
+		let (containers, items, support) = classify(children);
+
+		let sort = dlna::sort::SortCriteria::parse(&browse.sort_criteria);
+		let filter = dlna::filter::Filter::parse(&browse.filter);
+		let (result, number_returned, total_matches) = self.didl_page(
+			containers, items, support, &sort, &filter,
+			browse.starting_index, browse.requested_count);
+
+		respond_soap(dlna::types::BodyBrowseResponse {
+			browse_response: dlna::types::BrowseResponse {
+				number_returned,
+				total_matches,
+				update_id: update_id.into(),
+				result,
+			},
+		})
+	}
+	
+	fn call_dlna_browse_metadata(self, browse: dlna::types::Browse)
+		-> crate::Result<hyper::Response>
+	{
+		let object = self.0.root.lookup(&browse.object_id)?;
+		let filter = dlna::filter::Filter::parse(&browse.filter);
 		
 		let mut containers = Vec::new();
 		let mut items = Vec::new();
-		let mut support = Vec::new();
-
-		for entry in object.children()? {
-			match entry.file_type() {
-				crate::Type::Directory => containers.push(entry),
-				crate::Type::Image | crate::Type::Subtitles => support.push(entry),
-				crate::Type::Video => items.push(entry),
-				crate::Type::Other => continue,
-			}
+		
+		if object.is_dir() {
+			containers.push(self.didl_container(&*object, &filter));
+		} else {
+			let (_, _, mut support) = classify(self.0.root.lookup(object.parent_id())?.children()?);
+			support.sort_by(|l, r| crate::human_order(l.id(), r.id()));
+			
+			items.push(self.didl_item(&*object, &support, &filter));
 		}
-
-		containers.sort_by(|l, r| crate::human_order(l.id(), r.id()));
-		items.sort_by(|l, r| crate::human_order(l.id(), r.id()));
-		support.sort_by(|l, r| crate::human_order(l.id(), r.id()));
-
+		
 		respond_soap(dlna::types::BodyBrowseResponse {
 			browse_response: dlna::types::BrowseResponse {
 				number_returned: 1,
 				total_matches: 1,
-				update_id: 1,
-				result: dlna::types::Result(dlna::types::DidlLite {
-					xmlns: "urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/",
-					xmlns_dc: "http://purl.org/dc/elements/1.1/",
-					xmlns_upnp: "urn:schemas-upnp-org:metadata-1-0/upnp/",
-					containers: containers.into_iter().map(|entry| {
-						dlna::types::Container {
-							parent_id: entry.parent_id().to_string(),
-							id: entry.id().to_string(),
-							title: entry.title(),
-							restricted: true,
-							class: entry.dlna_class(),
-							_start_body: crate::xml::Body(()),
-						}
-					}).collect(),
-					items: items.into_iter().map(|entry| {
-						let path = percent_encoding::percent_encode(
-							entry.id().as_bytes(),
-							percent_encoding::DEFAULT_ENCODE_SET);
-						let url = format!("{}/video/{}", self.0.uri, path);
-
-						let mut item = dlna::types::Item {
-							parent_id: entry.parent_id().to_string(),
-							id: entry.id().to_string(),
-							title: entry.title(),
-							restricted: true,
-							class: entry.dlna_class(),
-							res: vec![
-								dlna::types::Res {
-									protocol_info: "http-get:*:video/x-matroska:*".to_string(),
-									uri: crate::xml::Body(url),
-								},
-							],
-						};
-
-						let prefix = entry.prefix();
-						let start = support
-							.binary_search_by_key(&prefix, |e| e.id())
-							.unwrap_or_else(|e| e);
-						for support in &support[start..] {
-							if !support.id().starts_with(prefix) {
-								break
-							}
-
-							let path = percent_encoding::percent_encode(
-								support.id().as_bytes(),
-								percent_encoding::DEFAULT_ENCODE_SET);
-
-							match support.file_type() {
-								crate::Type::Image => {
-									item.res.push(dlna::types::Res {
-										protocol_info: "http-get:*:image/jpeg:*".to_string(),
-										uri: crate::xml::Body(format!("{}/files/{}", self.0.uri, path)),
-									});
-								}
-								crate::Type::Subtitles => {
-								}
-
-								crate::Type::Directory => unreachable!(),
-								crate::Type::Video => unreachable!(),
-								crate::Type::Other => unreachable!(),
-							}
-						}
+				update_id: self.0.shared.update_ids.lock().unwrap()
+					.container(object.id()).into(),
+				result: dlna::types::Result(dlna::types::DidlLite::new(containers, items)),
+			},
+		})
+	}
+	
+	fn call_dlna_search(self, body: dlna::types::BodySearch) -> crate::Result<hyper::Response> {
+		let search = body.search;
+		let criteria = dlna::search::Criteria::parse(&search.search_criteria)?;
+		let sort = dlna::sort::SortCriteria::parse(&search.sort_criteria);
+		let filter = dlna::filter::Filter::parse(&search.filter);
+		let object = self.0.root.lookup(&search.container_id)?;
+		
+		let mut containers = Vec::new();
+		let mut items = Vec::new();
+		let mut support = Vec::new();
+		search_children(&*object, &criteria, &mut containers, &mut items, &mut support);
+		
+		let (result, number_returned, total_matches) = self.didl_page(
+			containers, items, support, &sort, &filter,
+			search.starting_index, search.requested_count);
+
+		respond_soap(dlna::types::BodySearchResponse {
+			search_response: dlna::types::SearchResponse {
+				number_returned,
+				total_matches,
+				update_id: self.0.shared.update_ids.lock().unwrap().system().into(),
+				result,
+			},
+		})
+	}
+	
+	/// Sort and page a listing, returning the result and the number of entries returned and
+	/// matched.
+	fn didl_page(&self,
+		mut containers: Vec<Box<dyn crate::Object>>,
+		mut items: Vec<Box<dyn crate::Object>>,
+		mut support: Vec<Box<dyn crate::Object>>,
+		sort: &dlna::sort::SortCriteria,
+		filter: &dlna::filter::Filter,
+		starting_index: u64, requested_count: u64,
+	) -> (dlna::types::Result, u64, u64) {
+		sort.sort(&mut containers);
+		sort.sort(&mut items);
+		support.sort_by(|l, r| crate::human_order(l.id(), r.id()));
+
+		let total_matches = containers.len() + items.len();
+		let page = page_range(starting_index, requested_count, total_matches);
+		let number_returned = page.len();
+		let skip_containers = page.start.min(containers.len());
+		let skip_items = page.start - skip_containers;
+		let containers: Vec<_> = containers.into_iter()
+			.skip(skip_containers)
+			.take(number_returned)
+			.collect();
+		let items: Vec<_> = items.into_iter()
+			.skip(skip_items)
+			.take(number_returned - containers.len())
+			.collect();
 
-						item
-					}).collect(),
-				}),
+		let result = dlna::types::Result(dlna::types::DidlLite::new(
+			containers.iter()
+				.map(|entry| self.didl_container(&**entry, filter))
+				.collect(),
+			items.iter()
+				.map(|entry| self.didl_item(&**entry, &support, filter))
+				.collect()));
+		
+		(result, number_returned as u64, total_matches as u64)
+	}
+	
+	fn didl_container(&self, entry: &dyn crate::Object, filter: &dlna::filter::Filter)
+		-> dlna::types::Container
+	{
+		dlna::types::Container {
+			parent_id: entry.parent_id().to_string(),
+			id: entry.id().to_string(),
+			title: entry.title(),
+			restricted: true,
+			class: entry.dlna_class(),
+			_start_body: crate::xml::Body(()),
+			date: if filter.includes("dc:date") {
+				entry.modified().map(format_date)
+			} else {
+				None
 			},
+			album_art_uri: if filter.includes("upnp:albumArtURI") {
+				self.folder_image(entry).into_iter().collect()
+			} else {
+				Vec::new()
+			},
+		}
+	}
+	
+	/// Find the cover image of a directory.
+	fn folder_image(&self, entry: &dyn crate::Object) -> Option<dlna::types::AlbumArtUri> {
+		if entry.is_view() { return None }
+		
+		// Look up the known names rather than listing every directory on the page.
+		let image = FOLDER_IMAGES.iter()
+			.filter_map(|name| self.0.root.lookup(&format!("{}/{}", entry.id(), name)).ok())
+			.find(|image| image.size().is_some())?;
+		
+		let profile = crate::thumbnail::Profile::JpegTn;
+		Some(dlna::types::AlbumArtUri {
+			profile_id: Some(profile.name()),
+			uri: crate::xml::Body(format!("{}/image/{}/{}",
+				self.0.uri,
+				profile.name(),
+				percent_encoding::percent_encode(
+					image.id().as_bytes(),
+					percent_encoding::DEFAULT_ENCODE_SET))),
 		})
 	}
+	
+	/// Describe an item, attaching any `support` files that share its prefix.
+	///
+	/// `support` must be sorted by id.
+	fn didl_item(&self,
+		entry: &dyn crate::Object,
+		support: &[Box<dyn crate::Object>],
+		filter: &dlna::filter::Filter,
+	) -> dlna::types::Item {
+		if entry.file_type() == crate::Type::Image {
+			return self.didl_photo(entry, filter)
+		}
+		
+		let path = percent_encoding::percent_encode(
+			entry.id().as_bytes(),
+			percent_encoding::DEFAULT_ENCODE_SET);
+		
+		let audio = entry.file_type() == crate::Type::Audio;
+		let needs_format = RES_FORMAT_PROPERTIES.iter().any(|p| filter.includes(p))
+			|| (audio && TAG_PROPERTIES.iter().any(|p| filter.includes(p)));
+		let format = if needs_format {
+			self.probe(entry)
+		} else {
+			None
+		};
+		let tags = match (audio, &format) {
+			(true, &Some(ref format)) => format.tags.clone(),
+			_ => crate::ffmpeg::Tags::default(),
+		};
+		let tag = |property, value: &Option<String>|
+			if filter.includes(property) { value.clone() } else { None };
+
+		let mut item = dlna::types::Item {
+			parent_id: entry.parent_id().to_string(),
+			id: entry.id().to_string(),
+			title: entry.title(),
+			restricted: true,
+			class: entry.dlna_class(),
+			date: if filter.includes("dc:date") {
+				entry.modified().map(format_date)
+			} else {
+				None
+			},
+			creator: tag("dc:creator", &tags.artist),
+			artist: tag("upnp:artist", &tags.artist),
+			album: tag("upnp:album", &tags.album),
+			genre: tag("upnp:genre", &tags.genre),
+			original_track_number: if filter.includes("upnp:originalTrackNumber") {
+				tags.track
+			} else {
+				None
+			},
+			album_art_uri: Vec::new(),
+			caption_info_ex: Vec::new(),
+			res: self.didl_media_res(entry, format.as_ref(), filter),
+		};
+
+		for support in support_for(support, entry.prefix()) {
+			let path = percent_encoding::percent_encode(
+				support.id().as_bytes(),
+				percent_encoding::DEFAULT_ENCODE_SET);
+
+			match support.file_type() {
+				crate::Type::Image => {
+					let url = format!("{}/files/{}", self.0.uri, path);
+					if item.album_art_uri.is_empty() && filter.includes("upnp:albumArtURI") {
+						item.album_art_uri.push(dlna::types::AlbumArtUri {
+							profile_id: None,
+							uri: crate::xml::Body(url.clone()),
+						});
+					}
+					let mut res = dlna::types::Res::new(
+						format!("http-get:*:{}:*", image_mime(support.id())), url);
+					if filter.includes("res@size") {
+						res.size = support.size();
+					}
+					item.res.push(res);
+				}
+				crate::Type::Subtitles => {
+					let source = match subtitle_format(&**support) {
+						Some(format) => format,
+						None => continue,
+					};
+					
+					// Offer the original and the formats that devices commonly require.
+					let mut formats = vec![source];
+					for &format in &[crate::ffmpeg::SubtitleFormat::SRT, crate::ffmpeg::SubtitleFormat::VTT] {
+						if !formats.contains(&format) {
+							formats.push(format);
+						}
+					}
+					for format in formats {
+						item.res.push(dlna::types::Res::new(
+							format!("http-get:*:{}:*", format.mime()),
+							subtitle_url(&self.0.uri, &**support, format)));
+					}
+					
+					if item.caption_info_ex.is_empty() && filter.includes("sec:CaptionInfoEx") {
+						item.caption_info_ex.push(dlna::types::CaptionInfoEx {
+							kind: crate::ffmpeg::SubtitleFormat::SRT.extension(),
+							uri: crate::xml::Body(subtitle_url(
+								&self.0.uri, &**support, crate::ffmpeg::SubtitleFormat::SRT)),
+						});
+					}
+				}
+
+				crate::Type::Audio => unreachable!(),
+				crate::Type::Directory => unreachable!(),
+				crate::Type::Video => unreachable!(),
+				crate::Type::Other => unreachable!(),
+			}
+		}
+		
+		if entry.file_type() == crate::Type::Video
+			&& item.album_art_uri.is_empty()
+			&& filter.includes("upnp:albumArtURI")
+		{
+			item.album_art_uri.push(dlna::types::AlbumArtUri {
+				profile_id: Some(crate::thumbnail::Profile::JpegTn.name()),
+				uri: crate::xml::Body(format!("{}/thumb/{}", self.0.uri, path)),
+			});
+		}
+
+		item
+	}
+	
+	/// Describe a standalone image with its generated thumbnails.
+	fn didl_photo(&self, entry: &dyn crate::Object, filter: &dlna::filter::Filter)
+		-> dlna::types::Item
+	{
+		let path = percent_encoding::percent_encode(
+			entry.id().as_bytes(),
+			percent_encoding::DEFAULT_ENCODE_SET);
+		let thumbnail_url = |profile: crate::thumbnail::Profile|
+			format!("{}/image/{}/{}", self.0.uri, profile.name(), path);
+		
+		let mut original = dlna::types::Res::new(
+			format!("http-get:*:{}:*", image_mime(entry.id())),
+			format!("{}/files/{}", self.0.uri, path));
+		if filter.includes("res@size") {
+			original.size = entry.size();
+		}
+		
+		let mut res = vec![original];
+		res.extend(crate::thumbnail::Profile::ALL.iter().map(|&profile|
+			dlna::types::Res::new(profile.protocol_info(), thumbnail_url(profile))));
+		
+		let mut album_art_uri = Vec::new();
+		if filter.includes("upnp:albumArtURI") {
+			album_art_uri.push(dlna::types::AlbumArtUri {
+				profile_id: Some(crate::thumbnail::Profile::JpegTn.name()),
+				uri: crate::xml::Body(thumbnail_url(crate::thumbnail::Profile::JpegTn)),
+			});
+		}
+		
+		dlna::types::Item {
+			parent_id: entry.parent_id().to_string(),
+			id: entry.id().to_string(),
+			title: entry.title(),
+			restricted: true,
+			class: entry.dlna_class(),
+			date: if filter.includes("dc:date") {
+				entry.modified().map(format_date)
+			} else {
+				None
+			},
+			creator: None,
+			artist: None,
+			album: None,
+			genre: None,
+			original_track_number: None,
+			album_art_uri,
+			caption_info_ex: Vec::new(),
+			res,
+		}
+	}
+	
+	/// Probe an entry for a listing, logging failures.
+	///
+	/// This blocks so it must only be called off the reactor.
+	fn probe(&self, entry: &dyn crate::Object) -> Option<crate::ffmpeg::Format> {
+		match self.0.format(entry).wait() {
+			Ok(format) => Some(format),
+			Err(e) => {
+				eprintln!("Error probing {:?}: {}", entry.id(), e.display_chain());
+				None
+			}
+		}
+	}
+	
+	/// The resources for playing an item, the original file followed by useful transcodes.
+	fn didl_media_res(&self,
+		entry: &dyn crate::Object,
+		format: Option<&crate::ffmpeg::Format>,
+		filter: &dlna::filter::Filter,
+	) -> Vec<dlna::types::Res> {
+		let path = percent_encoding::percent_encode(
+			entry.id().as_bytes(),
+			percent_encoding::DEFAULT_ENCODE_SET);
+		
+		let format = match format {
+			Some(format) => format,
+			// Without knowing the format let the server pick one for the requesting device.
+			None => return vec![dlna::types::Res::new(
+				"http-get:*:video/x-matroska:*".to_string(),
+				format!("{}/video/{}", self.0.uri, path))],
+		};
+		let url = |profile: &str| format!("{}/video/{}?profile={}", self.0.uri, path, profile);
+		
+		let mut original = self.didl_res(
+			format, format.protocol_info(false), url(crate::devices::ORIGINAL), filter);
+		if filter.includes("res@size") {
+			original.size = entry.size();
+		}
+		
+		let audio_stream = Some(format.preferred_audio_stream(&self.0.shared.languages))
+			.filter(|&i| i != format.default_audio_stream());
+		let mut res = crate::devices::profiles_for(format).into_iter()
+			.map(|profile| {
+				let target = format.transcode_for(&profile.device, audio_stream, None);
+				let mut res = self.didl_res(
+					&target, target.protocol_info(true), url(profile.name), filter);
+				if filter.includes("res@size") {
+					res.size = target.estimated_size();
+				}
+				res
+			})
+			.collect::<Vec<_>>();
+		
+		// The original plays the default audio stream so prefer transcodes if that isn't wanted.
+		if audio_stream.is_some() {
+			res.push(original);
+		} else {
+			res.insert(0, original);
+		}
+		res
+	}
+	
+	/// Describe a media resource, including the format details requested by the filter.
+	fn didl_res(&self,
+		format: &crate::ffmpeg::Format,
+		protocol_info: String,
+		url: String,
+		filter: &dlna::filter::Filter,
+	) -> dlna::types::Res {
+		let mut res = dlna::types::Res::new(protocol_info, url);
+		
+		if filter.includes("res@duration") {
+			res.duration = format.duration.map(format_duration);
+		}
+		if filter.includes("res@bitrate") {
+			res.bitrate = format.bitrate.map(|b| b / 8);
+		}
+		if filter.includes("res@resolution") {
+			res.resolution = format.resolution.map(|(w, h)| format!("{}x{}", w, h));
+		}
+		if filter.includes("res@nrAudioChannels") {
+			res.nr_audio_channels = format.audio_channels;
+		}
+		if filter.includes("res@sampleFrequency") {
+			res.sample_frequency = format.sample_rate;
+		}
+		
+		res
+	}
+}
+
+/// Recursively collect the descendants of `object` matching `criteria`.
+///
+/// All support files are collected so that they can be attached to matching items.
+fn search_children(
+	object: &dyn crate::Object,
+	criteria: &dlna::search::Criteria,
+	containers: &mut Vec<Box<dyn crate::Object>>,
+	items: &mut Vec<Box<dyn crate::Object>>,
+	support: &mut Vec<Box<dyn crate::Object>>,
+) {
+	let children = match object.children() {
+		Ok(children) => children,
+		Err(e) => {
+			eprintln!("Error searching {:?}: {}", object.id(), e.display_chain());
+			return
+		}
+	};
+	
+	let (child_containers, child_items, child_support) = classify(children);
+	
+	for entry in child_containers {
+		// Views only repeat objects found elsewhere, unless the search started inside one.
+		if entry.is_view() && !object.is_view() { continue }
+		
+		search_children(&*entry, criteria, containers, items, support);
+		if criteria.matches(&*entry) {
+			containers.push(entry);
+		}
+	}
+	items.extend(child_items.into_iter().filter(|entry| criteria.matches(&**entry)));
+	support.extend(child_support);
+}
+
+/// Split a directory listing into containers, items and support files.
+///
+/// Images that share the prefix of a media item are support files for it, other images are
+/// listed as photos.
+fn classify(children: Vec<Box<dyn crate::Object>>)
+	-> (Vec<Box<dyn crate::Object>>, Vec<Box<dyn crate::Object>>, Vec<Box<dyn crate::Object>>)
+{
+	let mut containers = Vec::new();
+	let mut items = Vec::new();
+	let mut support = Vec::new();
+	let mut images = Vec::new();
+	
+	for entry in children {
+		match entry.file_type() {
+			crate::Type::Directory => containers.push(entry),
+			crate::Type::Image => images.push(entry),
+			crate::Type::Subtitles => support.push(entry),
+			crate::Type::Audio | crate::Type::Video => items.push(entry),
+			crate::Type::Other => continue,
+		}
+	}
+	
+	for image in images {
+		if items.iter().any(|item| named_after(image.id(), item.prefix())) {
+			support.push(image);
+		} else {
+			items.push(image);
+		}
+	}
+	
+	(containers, items, support)
+}
+
+/// Whether `id` is a file named after the item with `prefix`, such as `movie.jpg` for `movie.mkv`.
+fn named_after(id: &str, prefix: &str) -> bool {
+	id.starts_with(prefix) && id[prefix.len()..].starts_with('.')
+}
+
+/// The status to reject a `selection` with, if it can't be served to `device`.
+///
+/// Devices that take subtitle streams themselves are sent the original streams, so a selected
+/// subtitle stream can't be honoured for them.
+fn selection_error(
+	format: &crate::ffmpeg::Format,
+	device: &crate::ffmpeg::Device,
+	selection: &crate::ffmpeg::Selection,
+) -> Option<hyper::StatusCode> {
+	match selection.subtitles {
+		Some(i) if i >= format.subtitles.len() => Some(hyper::StatusCode::NotFound),
+		Some(_) if device.soft_subtitles => Some(hyper::StatusCode::BadRequest),
+		_ => None,
+	}
+}
+
+/// The files in `support` named after the item with `prefix`.
+///
+/// `support` must be sorted by id.
+fn support_for<'a>(support: &'a [Box<dyn crate::Object>], prefix: &'a str)
+	-> impl Iterator<Item=&'a Box<dyn crate::Object>> + 'a
+{
+	let start = support
+		.binary_search_by_key(&prefix, |e| e.id())
+		.unwrap_or_else(|e| e);
+	support[start..].iter()
+		.take_while(move |support| support.id().starts_with(prefix))
+		.filter(move |support| named_after(support.id(), prefix))
+}
+
+fn subtitle_format(entry: &dyn crate::Object) -> Option<crate::ffmpeg::SubtitleFormat> {
+	entry.id().rsplit('.').next().and_then(crate::ffmpeg::SubtitleFormat::from_extension)
+}
+
+/// The URL of a subtitle file in `format`, converting it if required.
+fn subtitle_url(base: &str, entry: &dyn crate::Object, format: crate::ffmpeg::SubtitleFormat)
+	-> String
+{
+	let path = percent_encoding::percent_encode(
+		entry.id().as_bytes(),
+		percent_encoding::DEFAULT_ENCODE_SET);
+	if subtitle_format(entry) == Some(format) {
+		format!("{}/files/{}", base, path)
+	} else {
+		format!("{}/subtitles/{}/{}", base, format.extension(), path)
+	}
+}
+
+/// A `multipart/byteranges` boundary that is unlikely to appear in the content.
+fn multipart_boundary() -> String {
+	use std::hash::{BuildHasher, Hasher};
+	
+	let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+	hasher.write_u64(0);
+	format!("rustymedia-{:016x}", hasher.finish())
+}
+
+/// The MIME type of an image based on its extension.
+fn image_mime(id: &str) -> &'static str {
+	match id.rsplit('.').next().map(|e| e.to_ascii_lowercase()).as_ref().map(|e| &e[..]) {
+		Some("gif") => "image/gif",
+		Some("png") => "image/png",
+		_ => "image/jpeg",
+	}
+}
+
+/// Format a time as an ISO 8601 UTC date, as used by `dc:date`.
+fn format_date(time: std::time::SystemTime) -> String {
+	let secs = match time.duration_since(std::time::UNIX_EPOCH) {
+		Ok(d) => d.as_secs() as i64,
+		Err(e) => -(e.duration().as_secs() as i64),
+	};
+	let days = secs.div_euclid(86400);
+	let time = secs.rem_euclid(86400);
+
+	// Convert days since the epoch to a civil date in the proleptic Gregorian calendar.
+	let z = days + 719468;
+	let era = z.div_euclid(146097);
+	let doe = z.rem_euclid(146097);
+	let yoe = (doe - doe/1460 + doe/36524 - doe/146096) / 365;
+	let doy = doe - (365*yoe + yoe/4 - yoe/100);
+	let mp = (5*doy + 2) / 153;
+	let day = doy - (153*mp + 2)/5 + 1;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 };
+	let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+	format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+		year, month, day, time / 3600, time / 60 % 60, time % 60)
+}
+
+/// Format a duration in seconds as `H:MM:SS.mmm`, as used by `res@duration`.
+fn format_duration(secs: f64) -> String {
+	let millis = (secs * 1000.0).round() as u64;
+	format!("{}:{:02}:{:02}.{:03}",
+		millis / 3_600_000,
+		millis / 60_000 % 60,
+		millis / 1000 % 60,
+		millis % 1000)
+}
+
+/// Select the window of results requested by a Browse or Search.
+///
+/// A `requested_count` of zero means that all remaining results are wanted.
+fn page_range(starting_index: u64, requested_count: u64, total: usize)
+	-> std::ops::Range<usize>
+{
+	let start = starting_index.min(total as u64) as usize;
+	let end = match requested_count {
+		0 => total,
+		count => start.saturating_add(count.min(total as u64) as usize).min(total),
+	};
+	start..end
 }
 
 fn respond_ok(res: hyper::Response) -> BoxedResponse {
@@ -389,6 +1413,19 @@ fn respond_err(e: crate::error::Error) -> BoxedResponse {
 	Box::new(futures::future::err(e))
 }
 
+/// Respond to a GET or HEAD of an XML document.
+fn respond_xml<B: AsRef<[u8]> + Into<hyper::Body>>(req: dlna::Request, xml: B) -> BoxedResponse {
+	let mut response = hyper::Response::new()
+		.with_header(hyper::header::ContentType::xml())
+		.with_header(hyper::header::ContentLength(xml.as_ref().len() as u64));
+	match *req.req.method() {
+		hyper::Method::Get => response.set_body(xml),
+		hyper::Method::Head => {}
+		_ => return call_method_not_allowed(req),
+	}
+	respond_ok(response)
+}
+
 fn respond_soap<T: serde::Serialize + std::fmt::Debug>
 	(body: T) -> crate::error::Result<hyper::Response>
 {
@@ -402,14 +1439,45 @@ fn respond_soap<T: serde::Serialize + std::fmt::Debug>
 		.with_body(buf))
 }
 
-fn respond_soap_fault(msg: &str) -> BoxedResponse {
+/// Format `ContainerUpdateIDs` as comma separated `id,update_id` pairs.
+///
+/// Commas and backslashes in IDs are escaped with a backslash.
+fn format_container_update_ids(changes: &[(String, u32)]) -> String {
+	changes.iter()
+		.map(|&(ref id, update_id)| {
+			let id = id.replace('\\', "\\\\").replace(',', "\\,");
+			format!("{},{}", id, update_id)
+		})
+		.collect::<Vec<_>>()
+		.join(",")
+}
+
+fn soap_fault(msg: &str) -> crate::error::Result<hyper::Response> {
 	eprintln!("Reporting fault via soap: {:?}", msg);
-	Box::new(futures::future::result(respond_soap(dlna::types::BodyFault {
+	respond_soap(dlna::types::BodyFault {
 		fault: dlna::types::Fault {
 			faultcode: "SOAP-ENV:Client",
 			faultstring: msg,
 		},
-	})))
+	})
+}
+
+fn respond_soap_fault(msg: &str) -> BoxedResponse {
+	Box::new(futures::future::result(soap_fault(msg)))
+}
+
+/// Extract the name of the action requested from `service`.
+fn soap_action(req: &dlna::Request, service: &str) -> Result<String, BoxedResponse> {
+	let action = match req.req.headers().get::<Soapaction>() {
+		Some(action) => action.trim_matches('"'),
+		None => return Err(respond_soap_fault("No Soapaction header.")),
+	};
+	
+	let namespace = format!("urn:schemas-upnp-org:service:{}:1#", service);
+	if !action.starts_with(&namespace) {
+		return Err(respond_soap_fault(&format!("Unknown action namespace: {:?}", action)))
+	}
+	Ok(action[namespace.len()..].to_string())
 }
 
 fn call_not_found(req: dlna::Request) -> BoxedResponse {
@@ -422,6 +1490,10 @@ fn call_not_found(req: dlna::Request) -> BoxedResponse {
 		}))
 }
 
+fn respond_status(status: hyper::StatusCode) -> BoxedResponse {
+	respond_ok(hyper::Response::new().with_status(status))
+}
+
 fn call_method_not_allowed(req: dlna::Request) -> BoxedResponse {
 	eprintln!("405 {:?}", req.req);
 	respond_ok(
@@ -452,3 +1524,85 @@ impl hyper::server::Service for ServerRef {
 	}
 }
 
+
+#[test]
+fn test_page_range() {
+	assert_eq!(page_range(0, 0, 0), 0..0);
+	assert_eq!(page_range(0, 0, 120), 0..120);
+	assert_eq!(page_range(0, 50, 120), 0..50);
+	assert_eq!(page_range(50, 50, 120), 50..100);
+	assert_eq!(page_range(100, 50, 120), 100..120);
+	assert_eq!(page_range(150, 50, 120), 120..120);
+	assert_eq!(page_range(10, u64::max_value(), 120), 10..120);
+}
+
+#[test]
+fn test_format_date() {
+	let date = |secs| format_date(std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs));
+
+	assert_eq!(date(0), "1970-01-01T00:00:00");
+	assert_eq!(date(951782400), "2000-02-29T00:00:00");
+	assert_eq!(date(1538395261), "2018-10-01T12:01:01");
+}
+
+#[test]
+fn test_format_duration() {
+	assert_eq!(format_duration(0.0), "0:00:00.000");
+	assert_eq!(format_duration(61.5), "0:01:01.500");
+	assert_eq!(format_duration(5025.0004), "1:23:45.000");
+}
+
+#[test]
+fn test_format_container_update_ids() {
+	assert_eq!(format_container_update_ids(&[]), "");
+	assert_eq!(
+		format_container_update_ids(&[("a/b".to_string(), 3), ("c,d\\".to_string(), 4)]),
+		"a/b,3,c\\,d\\\\,4");
+}
+
+#[test]
+fn test_named_after() {
+	assert!(named_after("dir/IMG_1.jpg", "dir/IMG_1"));
+	assert!(named_after("dir/IMG_1.poster.jpg", "dir/IMG_1"));
+	assert!(!named_after("dir/IMG_10.jpg", "dir/IMG_1"));
+	assert!(!named_after("dir/IMG_1", "dir/IMG_1"));
+}
+
+#[test]
+fn test_support_for() {
+	use crate::Object;
+	
+	let root = crate::local::Object::new_root("dir".to_string(), "/nonexistent").unwrap();
+	let mut support = [
+		"Episode 1.en.srt",
+		"Episode 1.jpg",
+		"Episode 10.srt",
+		"Episode 1x.en.srt",
+	].iter().map(|name| root.lookup(name).unwrap()).collect::<Vec<_>>();
+	support.sort_by(|a, b| a.id().cmp(b.id()));
+	
+	let episode_1 = root.lookup("Episode 1.mkv").unwrap();
+	let ids = support_for(&support, episode_1.prefix()).map(|s| s.id()).collect::<Vec<_>>();
+	assert_eq!(ids, ["dir/Episode 1.en.srt", "dir/Episode 1.jpg"]);
+	
+	let episode_10 = root.lookup("Episode 10.mkv").unwrap();
+	let ids = support_for(&support, episode_10.prefix()).map(|s| s.id()).collect::<Vec<_>>();
+	assert_eq!(ids, ["dir/Episode 10.srt"]);
+}
+
+#[test]
+fn test_selection_error() {
+	let format: crate::ffmpeg::Format = serde_json::from_str(
+		r#"{"container": "MKV", "subtitles": [{"codec": "subrip"}]}"#).unwrap();
+	let selection = |subtitles| crate::ffmpeg::Selection{subtitles, ..Default::default()};
+	let mkv = crate::devices::profile("mkv").unwrap();
+	let original = crate::devices::profile(crate::devices::ORIGINAL).unwrap();
+	
+	assert_eq!(selection_error(&format, mkv, &selection(None)), None);
+	assert_eq!(selection_error(&format, mkv, &selection(Some(0))), None);
+	assert_eq!(selection_error(&format, mkv, &selection(Some(1))),
+		Some(hyper::StatusCode::NotFound));
+	assert_eq!(selection_error(&format, original, &selection(None)), None);
+	assert_eq!(selection_error(&format, original, &selection(Some(0))),
+		Some(hyper::StatusCode::BadRequest));
+}
@@ -0,0 +1,133 @@
+use std;
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+enum Property {
+	Class,
+	Date,
+	Size,
+	Title,
+}
+
+#[derive(Debug,PartialEq,PartialOrd)]
+enum Value {
+	Class(&'static str),
+	Date(Option<std::time::SystemTime>),
+	Size(Option<u64>),
+	Title(String),
+}
+
+impl Property {
+	fn parse(name: &str) -> Option<Self> {
+		match name {
+			"dc:date" => Some(Property::Date),
+			"dc:title" => Some(Property::Title),
+			"res@size" => Some(Property::Size),
+			"upnp:class" => Some(Property::Class),
+			_ => None,
+		}
+	}
+
+	fn value(self, object: &dyn crate::Object) -> Value {
+		match self {
+			Property::Class => Value::Class(object.dlna_class()),
+			Property::Date => Value::Date(object.modified()),
+			Property::Size => Value::Size(object.size()),
+			Property::Title => Value::Title(object.title()),
+		}
+	}
+}
+
+impl Value {
+	fn compare(&self, that: &Value) -> std::cmp::Ordering {
+		match (self, that) {
+			(&Value::Title(ref l), &Value::Title(ref r)) => crate::human_order(l, r),
+			(l, r) => l.partial_cmp(r).unwrap_or(std::cmp::Ordering::Equal),
+		}
+	}
+}
+
+/// A parsed `SortCriteria` list.
+///
+/// Objects that compare equal on every requested property fall back to `human_order` on their
+/// IDs, which is also the order used when no criteria are given. Unsupported properties are
+/// ignored as clients often ask for more than `SortCapabilities` offers.
+#[derive(Debug,PartialEq)]
+pub struct SortCriteria(Vec<(Property, bool)>);
+
+impl SortCriteria {
+	pub fn parse(input: &str) -> Self {
+		let criteria = input.split(',')
+			.map(|s| s.trim())
+			.filter(|s| !s.is_empty())
+			.filter_map(|s| {
+				let (name, descending) = if s.starts_with('-') {
+					(&s[1..], true)
+				} else if s.starts_with('+') {
+					(&s[1..], false)
+				} else {
+					(s, false)
+				};
+				match Property::parse(name) {
+					Some(property) => Some((property, descending)),
+					None => {
+						eprintln!("Ignoring unsupported sort property {:?}", name);
+						None
+					}
+				}
+			})
+			.collect();
+		SortCriteria(criteria)
+	}
+
+	pub fn sort(&self, objects: &mut Vec<Box<dyn crate::Object>>) {
+		let mut keyed = objects.drain(..)
+			.map(|object| {
+				let values = self.0.iter()
+					.map(|&(property, _)| property.value(&*object))
+					.collect::<Vec<_>>();
+				(values, object)
+			})
+			.collect::<Vec<_>>();
+
+		keyed.sort_by(|&(ref lvalues, ref l), &(ref rvalues, ref r)| {
+			self.0.iter()
+				.zip(lvalues.iter().zip(rvalues))
+				.map(|(&(_, descending), (l, r))| {
+					let ordering = l.compare(r);
+					if descending { ordering.reverse() } else { ordering }
+				})
+				.find(|ordering| *ordering != std::cmp::Ordering::Equal)
+				.unwrap_or_else(|| crate::human_order(l.id(), r.id()))
+		});
+
+		objects.extend(keyed.into_iter().map(|(_, object)| object));
+	}
+}
+
+#[test]
+fn test_parse() {
+	assert_eq!(SortCriteria::parse(""), SortCriteria(vec![]));
+	assert_eq!(
+		SortCriteria::parse("-dc:date,+dc:title"),
+		SortCriteria(vec![(Property::Date, true), (Property::Title, false)]));
+	assert_eq!(
+		SortCriteria::parse(" res@size , upnp:class"),
+		SortCriteria(vec![(Property::Size, false), (Property::Class, false)]));
+	assert_eq!(SortCriteria::parse("+upnp:artist"), SortCriteria(vec![]));
+	assert_eq!(
+		SortCriteria::parse("+upnp:artist,-dc:creator,+dc:title"),
+		SortCriteria(vec![(Property::Title, false)]));
+}
+
+#[test]
+fn test_value_cmp() {
+	use std::cmp::Ordering::*;
+
+	assert_eq!(Value::Title("ep 2".to_string()).compare(&Value::Title("ep 10".to_string())), Less);
+	assert_eq!(Value::Size(None).compare(&Value::Size(Some(0))), Less);
+	assert_eq!(Value::Size(Some(5)).compare(&Value::Size(Some(3))), Greater);
+
+	let early = std::time::UNIX_EPOCH;
+	let late = early + std::time::Duration::from_secs(60);
+	assert_eq!(Value::Date(Some(late)).compare(&Value::Date(Some(early))), Greater);
+}
@@ -14,6 +14,12 @@ pub struct Body {
 	pub browse: Browse,
 }
 
+#[derive(Debug,Deserialize)]
+#[serde(rename_all="PascalCase")]
+pub struct BodySearch {
+	pub search: Search,
+}
+
 #[derive(Debug,Serialize)]
 #[serde(rename="Body",rename_all="PascalCase")]
 pub struct BodyFault<'a> {
@@ -32,6 +38,109 @@ pub struct BodyBrowseResponse {
 	pub browse_response: BrowseResponse,
 }
 
+#[derive(Debug,Serialize)]
+#[serde(rename="Body",rename_all="PascalCase")]
+pub struct BodyGetSearchCapabilitiesResponse {
+	pub get_search_capabilities_response: GetSearchCapabilitiesResponse,
+}
+
+#[derive(Debug,Serialize)]
+#[serde(rename_all="PascalCase")]
+pub struct GetSearchCapabilitiesResponse {
+	pub _start_body: crate::xml::Body<()>,
+	pub search_caps: &'static str,
+}
+
+#[derive(Debug,Serialize)]
+#[serde(rename="Body",rename_all="PascalCase")]
+pub struct BodyGetSortCapabilitiesResponse {
+	pub get_sort_capabilities_response: GetSortCapabilitiesResponse,
+}
+
+#[derive(Debug,Serialize)]
+#[serde(rename_all="PascalCase")]
+pub struct GetSortCapabilitiesResponse {
+	pub _start_body: crate::xml::Body<()>,
+	pub sort_caps: &'static str,
+}
+
+#[derive(Debug,Serialize)]
+#[serde(rename="Body")]
+pub struct BodyGetSystemUpdateIdResponse {
+	#[serde(rename="GetSystemUpdateIDResponse")]
+	pub get_system_update_id_response: GetSystemUpdateIdResponse,
+}
+
+#[derive(Debug,Serialize)]
+#[serde(rename="GetSystemUpdateIDResponse",rename_all="PascalCase")]
+pub struct GetSystemUpdateIdResponse {
+	pub _start_body: crate::xml::Body<()>,
+	pub id: u32,
+}
+
+#[derive(Debug,Deserialize)]
+#[serde(rename_all="PascalCase")]
+pub struct BodyGetCurrentConnectionInfo {
+	pub get_current_connection_info: GetCurrentConnectionInfo,
+}
+
+#[derive(Debug,Deserialize)]
+pub struct GetCurrentConnectionInfo {
+	#[serde(rename="ConnectionID")]
+	pub connection_id: i32,
+}
+
+#[derive(Debug,Serialize)]
+#[serde(rename="Body",rename_all="PascalCase")]
+pub struct BodyGetCurrentConnectionInfoResponse {
+	pub get_current_connection_info_response: GetCurrentConnectionInfoResponse,
+}
+
+#[derive(Debug,Serialize)]
+#[serde(rename_all="PascalCase")]
+pub struct GetCurrentConnectionInfoResponse {
+	pub _start_body: crate::xml::Body<()>,
+	#[serde(rename="RcsID")]
+	pub rcs_id: i32,
+	#[serde(rename="AVTransportID")]
+	pub av_transport_id: i32,
+	pub protocol_info: &'static str,
+	pub peer_connection_manager: &'static str,
+	#[serde(rename="PeerConnectionID")]
+	pub peer_connection_id: i32,
+	pub direction: &'static str,
+	pub status: &'static str,
+}
+
+#[derive(Debug,Serialize)]
+#[serde(rename="Body",rename_all="PascalCase")]
+pub struct BodyGetCurrentConnectionIdsResponse {
+	#[serde(rename="GetCurrentConnectionIDsResponse")]
+	pub get_current_connection_ids_response: GetCurrentConnectionIdsResponse,
+}
+
+#[derive(Debug,Serialize)]
+#[serde(rename="GetCurrentConnectionIDsResponse")]
+pub struct GetCurrentConnectionIdsResponse {
+	pub _start_body: crate::xml::Body<()>,
+	#[serde(rename="ConnectionIDs")]
+	pub connection_ids: &'static str,
+}
+
+#[derive(Debug,Serialize)]
+#[serde(rename="Body",rename_all="PascalCase")]
+pub struct BodyGetProtocolInfoResponse {
+	pub get_protocol_info_response: GetProtocolInfoResponse,
+}
+
+#[derive(Debug,Serialize)]
+#[serde(rename_all="PascalCase")]
+pub struct GetProtocolInfoResponse {
+	pub _start_body: crate::xml::Body<()>,
+	pub source: String,
+	pub sink: &'static str,
+}
+
 #[derive(Debug,Deserialize)]
 #[serde(rename_all="PascalCase")]
 pub struct Browse {
@@ -53,6 +162,33 @@ pub struct BrowseResponse {
 	pub update_id: u64,
 }
 
+#[derive(Debug,Serialize)]
+#[serde(rename="Body",rename_all="PascalCase")]
+pub struct BodySearchResponse {
+	pub search_response: SearchResponse,
+}
+
+#[derive(Debug,Deserialize)]
+#[serde(rename_all="PascalCase")]
+pub struct Search {
+	#[serde(rename="ContainerID")]
+	pub container_id: String,
+	pub search_criteria: String,
+	pub filter: String,
+	pub starting_index: u64,
+	pub requested_count: u64,
+	pub sort_criteria: String,
+}
+
+#[derive(Debug,Serialize)]
+#[serde(rename_all="PascalCase")]
+pub struct SearchResponse {
+	pub result: Result,
+	pub number_returned: u64,
+	pub total_matches: u64,
+	pub update_id: u64,
+}
+
 #[derive(Debug)]
 pub struct Result(pub DidlLite);
 
@@ -78,10 +214,28 @@ pub struct DidlLite {
 	pub xmlns_dc: &'static str,
 	#[serde(rename="xmlns:upnp")]
 	pub xmlns_upnp: &'static str,
+	#[serde(rename="xmlns:dlna")]
+	pub xmlns_dlna: &'static str,
+	#[serde(rename="xmlns:sec")]
+	pub xmlns_sec: &'static str,
 	pub containers: Vec<Container>,
 	pub items: Vec<Item>,
 }
 
+impl DidlLite {
+	pub fn new(containers: Vec<Container>, items: Vec<Item>) -> Self {
+		DidlLite {
+			xmlns: "urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/",
+			xmlns_dc: "http://purl.org/dc/elements/1.1/",
+			xmlns_upnp: "urn:schemas-upnp-org:metadata-1-0/upnp/",
+			xmlns_dlna: "urn:schemas-dlna-org:metadata-1-0/",
+			xmlns_sec: "http://www.sec.co.kr/",
+			containers,
+			items,
+		}
+	}
+}
+
 #[derive(Debug,Serialize)]
 #[serde(rename="container",rename_all="camelCase")]
 pub struct Container {
@@ -95,9 +249,9 @@ pub struct Container {
 	pub title: String,
 	#[serde(rename="upnp:class")]
 	pub class: &'static str,
-	
-	// #[serde(rename="albumArtURI")]
-	// pub album_art_uri: Vec<AlbumArtUri>,
+	#[serde(rename="dc:date")]
+	pub date: Option<String>,
+	pub album_art_uri: Vec<AlbumArtUri>,
 }
 
 #[derive(Debug,Serialize)]
@@ -114,23 +268,67 @@ pub struct Item {
 	pub title: String,
 	#[serde(rename="upnp:class")]
 	pub class: &'static str,
+	#[serde(rename="dc:date")]
+	pub date: Option<String>,
+	#[serde(rename="dc:creator")]
+	pub creator: Option<String>,
+	#[serde(rename="upnp:artist")]
+	pub artist: Option<String>,
+	#[serde(rename="upnp:album")]
+	pub album: Option<String>,
+	#[serde(rename="upnp:genre")]
+	pub genre: Option<String>,
+	#[serde(rename="upnp:originalTrackNumber")]
+	pub original_track_number: Option<u32>,
+	pub album_art_uri: Vec<AlbumArtUri>,
+	pub caption_info_ex: Vec<CaptionInfoEx>,
 }
 
 #[derive(Debug,Serialize)]
 #[serde(rename="res",rename_all="camelCase")]
 pub struct Res {
-	// pub size: u64,
-	
-	// Resolution in XXXxYYYY format.
-	// pub resolution: String,
+	pub size: Option<u64>,
+	/// Duration in H:MM:SS.mmm format.
+	pub duration: Option<String>,
+	/// Bitrate in bytes per second.
+	pub bitrate: Option<u64>,
+	/// Resolution in XXXxYYYY format.
+	pub resolution: Option<String>,
+	pub nr_audio_channels: Option<u32>,
+	pub sample_frequency: Option<u32>,
 	
 	pub protocol_info: String,
 	pub uri: crate::xml::Body<String>,
 }
 
+impl Res {
+	pub fn new(protocol_info: String, uri: String) -> Self {
+		Res {
+			size: None,
+			duration: None,
+			bitrate: None,
+			resolution: None,
+			nr_audio_channels: None,
+			sample_frequency: None,
+			protocol_info,
+			uri: crate::xml::Body(uri),
+		}
+	}
+}
+
 #[derive(Debug,Serialize)]
+#[serde(rename="upnp:albumArtURI")]
 pub struct AlbumArtUri {
-	#[serde(rename="profileID")]
-	pub profile_id: String,
+	#[serde(rename="dlna:profileID")]
+	pub profile_id: Option<&'static str>,
+	pub uri: crate::xml::Body<String>,
+}
+
+/// Samsung's extension for sidecar subtitles.
+#[derive(Debug,Serialize)]
+#[serde(rename="sec:CaptionInfoEx")]
+pub struct CaptionInfoEx {
+	#[serde(rename="sec:type")]
+	pub kind: &'static str,
 	pub uri: crate::xml::Body<String>,
 }
@@ -53,12 +53,15 @@ fn add_input(input: Input, exec: &crate::Executors, cmd: &mut std::process::Comm
 	Ok(())
 }
 
-#[derive(Clone,Debug,PartialEq)]
+#[derive(Clone,Debug,Deserialize,PartialEq,Serialize)]
 pub enum ContainerFormat {
+	FLAC,
 	MKV,
 	MOV,
+	MP3,
 	MP4,
 	MPEGTS,
+	OGG,
 	WAV,
 	WEBM,
 	
@@ -66,12 +69,30 @@ pub enum ContainerFormat {
 }
 
 impl ContainerFormat {
+	pub fn mime(&self) -> &'static str {
+		match *self {
+			ContainerFormat::FLAC => "audio/flac",
+			ContainerFormat::MKV => "video/x-matroska",
+			ContainerFormat::MOV => "video/quicktime",
+			ContainerFormat::MP3 => "audio/mpeg",
+			ContainerFormat::MP4 => "video/mp4",
+			ContainerFormat::MPEGTS => "video/mp2t",
+			ContainerFormat::OGG => "audio/ogg",
+			ContainerFormat::WAV => "audio/wav",
+			ContainerFormat::WEBM => "video/webm",
+			ContainerFormat::Other(_) => "application/octet-stream",
+		}
+	}
+	
 	fn ffmpeg_encoder_and_flags(&self) -> &'static [&'static str] {
 		match *self {
+			ContainerFormat::FLAC => &["flac"],
 			ContainerFormat::MKV => &["matroska"],
 			ContainerFormat::MPEGTS => &["mpegts"],
 			ContainerFormat::MOV => &["mov", "-movflags", "+frag_keyframe"],
+			ContainerFormat::MP3 => &["mp3"],
 			ContainerFormat::MP4 => &["ismv", "-movflags", "+frag_keyframe"],
+			ContainerFormat::OGG => &["ogg"],
 			ContainerFormat::WAV => 
 				unreachable!("WAV shouldn't be used because ffmpeg creates invalid WAV files."),
 			ContainerFormat::WEBM => &["webm"],
@@ -79,9 +100,26 @@ impl ContainerFormat {
 				unreachable!("Unknown codec {:?} should never be used as a target.", s),
 		}
 	}
+	
+	/// If audio in `format` can be stored in this container.
+	pub fn carries(&self, audio: &AudioFormat) -> bool {
+		use self::AudioFormat::*;
+		
+		match *self {
+			ContainerFormat::FLAC => *audio == FLAC,
+			ContainerFormat::MKV => true,
+			ContainerFormat::MOV | ContainerFormat::MP4 | ContainerFormat::MPEGTS =>
+				*audio == AAC || *audio == MP3,
+			ContainerFormat::MP3 => *audio == MP3,
+			ContainerFormat::OGG => *audio == FLAC || *audio == Opus || *audio == Vorbis,
+			ContainerFormat::WAV => false,
+			ContainerFormat::WEBM => *audio == Opus || *audio == Vorbis,
+			ContainerFormat::Other(_) => false,
+		}
+	}
 }
 
-#[derive(Clone,Debug,PartialEq)]
+#[derive(Clone,Debug,Deserialize,PartialEq,Serialize)]
 pub enum AudioFormat {
 	AAC,
 	FLAC,
@@ -106,7 +144,7 @@ impl AudioFormat {
 	}
 }
 
-#[derive(Clone,Debug,PartialEq)]
+#[derive(Clone,Debug,Deserialize,PartialEq,Serialize)]
 pub enum VideoFormat {
 	H264,
 	HEVC,
@@ -128,42 +166,367 @@ impl VideoFormat {
 	}
 }
 
-#[derive(Debug)]
+/// Text subtitle formats that can be served as sidecar files.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum SubtitleFormat {
+	ASS,
+	SRT,
+	VTT,
+}
+
+impl SubtitleFormat {
+	pub fn from_extension(ext: &str) -> Option<SubtitleFormat> {
+		match &ext.to_ascii_lowercase()[..] {
+			"ass" | "ssa" => Some(SubtitleFormat::ASS),
+			"srt" => Some(SubtitleFormat::SRT),
+			"vtt" => Some(SubtitleFormat::VTT),
+			_ => None,
+		}
+	}
+	
+	pub fn extension(self) -> &'static str {
+		match self {
+			SubtitleFormat::ASS => "ass",
+			SubtitleFormat::SRT => "srt",
+			SubtitleFormat::VTT => "vtt",
+		}
+	}
+	
+	pub fn mime(self) -> &'static str {
+		match self {
+			SubtitleFormat::ASS => "text/x-ssa",
+			SubtitleFormat::SRT => "text/srt",
+			SubtitleFormat::VTT => "text/vtt",
+		}
+	}
+	
+	fn ffmpeg_format(self) -> &'static str {
+		match self {
+			SubtitleFormat::ASS => "ass",
+			SubtitleFormat::SRT => "srt",
+			SubtitleFormat::VTT => "webvtt",
+		}
+	}
+}
+
+#[derive(Clone,Debug,Deserialize,Serialize)]
 pub struct Format {
 	container: ContainerFormat,
 	audio: Option<AudioFormat>,
 	video: Option<VideoFormat>,
+	
+	/// Duration in seconds.
+	pub duration: Option<f64>,
+	/// Overall bitrate in bits per second.
+	pub bitrate: Option<u64>,
+	/// Video (width, height).
+	pub resolution: Option<(u32, u32)>,
+	pub audio_channels: Option<u32>,
+	pub sample_rate: Option<u32>,
+	#[serde(default)]
+	pub tags: Tags,
+	/// Audio streams in file order. `audio`, `audio_channels` and `sample_rate` describe the
+	/// default one.
+	#[serde(default)]
+	pub audio_streams: Vec<AudioStream>,
+	/// The source audio stream that was kept, as an index into `audio_streams`.
+	#[serde(default)]
+	pub audio_stream: Option<usize>,
+	/// Embedded subtitle streams in file order.
+	#[serde(default)]
+	pub subtitles: Vec<SubtitleStream>,
+	/// The subtitle stream rendered into the video, as an index into `subtitles`.
+	#[serde(default)]
+	pub burned_subtitles: Option<usize>,
+	/// The position in the source that the output starts at, in seconds.
+	#[serde(default)]
+	pub start: Option<f64>,
+}
+
+/// Bitrate of encoded audio in bits per second.
+const AUDIO_BITRATE: u64 = 192_000;
+
+/// Upper limit for the bitrate of encoded video in bits per second.
+const MAX_VIDEO_BITRATE: u64 = 8_000_000;
+
+/// Transcodes are estimated this much larger than their bitrate suggests so that the end of the
+/// stream is padded rather than truncated.
+const SIZE_MARGIN: f64 = 1.1;
+
+/// The bitrate to encode video at, in bits per second.
+fn video_bitrate(source: Option<u64>) -> u64 {
+	source.map_or(MAX_VIDEO_BITRATE, |b| b.min(MAX_VIDEO_BITRATE))
+}
+
+/// The streams and position of a source requested by a client.
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub struct Selection {
+	/// An audio stream, the file's default if not set.
+	pub audio: Option<usize>,
+	/// A subtitle stream to show.
+	pub subtitles: Option<usize>,
+	/// The position to start at in seconds.
+	pub start: Option<f64>,
+}
+
+#[derive(Clone,Debug,Deserialize,PartialEq,Serialize)]
+pub struct AudioStream {
+	pub codec: AudioFormat,
+	/// Bitrate in bits per second.
+	#[serde(default)]
+	pub bitrate: Option<u64>,
+	pub channels: Option<u32>,
+	pub sample_rate: Option<u32>,
+	pub language: Option<String>,
+	pub title: Option<String>,
+	/// Whether the stream is flagged to be played by default.
+	pub default: bool,
+}
+
+#[derive(Clone,Debug,Deserialize,PartialEq,Serialize)]
+pub struct SubtitleStream {
+	/// The ffmpeg codec name.
+	pub codec: String,
+	pub language: Option<String>,
+	pub title: Option<String>,
+}
+
+impl SubtitleStream {
+	/// Whether the subtitles are images rather than text.
+	pub fn is_bitmap(&self) -> bool {
+		match self.codec.as_ref() {
+			"dvb_subtitle" | "dvd_subtitle" | "hdmv_pgs_subtitle" | "xsub" => true,
+			_ => false,
+		}
+	}
+}
+
+/// Descriptive tags such as ID3 frames or Vorbis comments.
+#[derive(Clone,Debug,Default,Deserialize,PartialEq,Serialize)]
+pub struct Tags {
+	pub title: Option<String>,
+	pub artist: Option<String>,
+	pub album_artist: Option<String>,
+	pub album: Option<String>,
+	pub genre: Option<String>,
+	pub track: Option<u32>,
+}
+
+impl Tags {
+	/// Extract known tags, ignoring the case of the keys.
+	fn parse(raw: &std::collections::HashMap<String, String>) -> Tags {
+		let get = |keys: &[&str]| raw.iter()
+			.find(|&(k, v)| keys.iter().any(|key| k.eq_ignore_ascii_case(key)) && !v.trim().is_empty())
+			.map(|(_, v)| v.trim().to_string());
+		
+		Tags {
+			title: get(&["title"]),
+			artist: get(&["artist"]),
+			album_artist: get(&["album_artist", "albumartist", "album artist"]),
+			album: get(&["album"]),
+			genre: get(&["genre"]),
+			// Track numbers are often of the form "3/12".
+			track: get(&["track", "tracknumber"])
+				.and_then(|t| t.split('/').next().and_then(|t| t.trim().parse().ok())),
+		}
+	}
 }
 
 impl Format {
+	/// The audio stream that players pick when given the file as-is.
+	pub fn default_audio_stream(&self) -> usize {
+		self.audio_streams.iter().position(|s| s.default).unwrap_or(0)
+	}
+	
+	/// The first audio stream in one of `languages`, falling back to the default stream.
+	pub fn preferred_audio_stream(&self, languages: &[String]) -> usize {
+		languages.iter()
+			.filter_map(|language| self.audio_streams.iter().position(|s|
+				s.language.as_ref().map_or(false, |l| l.eq_ignore_ascii_case(language))))
+			.next()
+			.unwrap_or_else(|| self.default_audio_stream())
+	}
+	
+	pub fn has_video(&self) -> bool {
+		self.video.is_some()
+	}
+	
+	/// The protocolInfo of a `res` serving this format, `converted` is set for transcodes.
+	pub fn protocol_info(&self, converted: bool) -> String {
+		format!("http-get:*:{}:{}", self.mime(), self.content_features(converted))
+	}
+	
+	/// The MIME type of media in this format.
+	pub fn mime(&self) -> &'static str {
+		mime(&self.container, self.video.as_ref())
+	}
+	
+	/// The DLNA parameters of this format, the fourth field of its protocolInfo.
+	pub fn content_features(&self, converted: bool) -> String {
+		let mut params = Vec::new();
+		if let Some(profile) = dlna_profile(&self.container, self.video.as_ref(), self.audio.as_ref()) {
+			params.push(format!("DLNA.ORG_PN={}", profile));
+		}
+		// Originals support byte ranges, transcodes can be started at any time.
+		params.push(format!("DLNA.ORG_OP={}", if converted { "10" } else { "01" }));
+		params.push(format!("DLNA.ORG_CI={}", if converted { 1 } else { 0 }));
+		params.push(format!("DLNA.ORG_FLAGS={}", DLNA_FLAGS));
+		params.join(";")
+	}
+	
+	/// The expected size of a transcode to this format.
+	pub fn estimated_size(&self) -> Option<u64> {
+		let duration = self.duration?;
+		let bitrate = self.bitrate?;
+		Some((duration * bitrate as f64 / 8.0 * SIZE_MARGIN) as u64)
+	}
+	
+	/// Whether this can be sent to `device` as-is.
+	///
+	/// Media with burned in subtitles is never served unless those subtitles were requested so
+	/// this doesn't check them.
 	pub fn compatible_with(&self, device: &Device) -> bool {
+		let containers = if self.video.is_some() { device.container } else { device.audio_container };
+		
 		// Empty container is a hack to indicate that everything is supported.
 		return device.container.is_empty()
-			|| (device.container.contains(&self.container)
+			|| (containers.contains(&self.container)
 				&& self.video.as_ref().map(|f| device.video.contains(&f)).unwrap_or(true)
 				&& self.audio.as_ref().map(|f| device.audio.contains(&f)).unwrap_or(true));
 	}
 
-	pub fn transcode_for(&self, device: &Device) -> Format {
-		// Warning: Devices may have empty supported arrays to indicate they will take anything.
+	/// Whether a transcode to `other` produces the same container, codecs and streams as this.
+	pub fn same_output(&self, other: &Format) -> bool {
+		self.container == other.container
+			&& self.video == other.video
+			&& self.audio == other.audio
+			&& self.audio_stream == other.audio_stream
+			&& self.burned_subtitles == other.burned_subtitles
+	}
+
+	/// The format to transcode to for `device`.
+	///
+	/// `audio_stream` selects an audio stream other than the default and `burned_subtitles` a
+	/// subtitle stream to render into the video.
+	pub fn transcode_for(&self,
+		device: &Device,
+		audio_stream: Option<usize>,
+		burned_subtitles: Option<usize>,
+	) -> Format {
+		let source_audio = audio_stream.and_then(|i| self.audio_streams.get(i));
+		
+		// Devices may have empty supported arrays to indicate they will take anything.
 		let video = self.video.as_ref()
-			.and_then(|f| if device.video.contains(f) { Some(f) } else { device.video.first() });
-		let audio = self.audio.as_ref()
-			.and_then(|f| if device.audio.contains(f) { Some(f) } else { device.audio.first() });
+			.and_then(|f| if device.video.is_empty() || device.video.contains(f) {
+				Some(f)
+			} else {
+				device.video.first()
+			});
+		// Burning in subtitles requires encoding, pick a format we know how to encode.
+		let video = match (video, burned_subtitles) {
+			(Some(&VideoFormat::Other(_)), Some(_)) => Some(&VideoFormat::H264),
+			(video, _) => video,
+		};
+		let audio = source_audio.map(|s| &s.codec).or(self.audio.as_ref())
+			.and_then(|f| if device.audio.is_empty() || device.audio.contains(f) {
+				Some(f)
+			} else {
+				device.audio.first()
+			});
+		
+		// Predict the bitrate from the streams that are copied and the rates used for encoding.
+		let copy_video = video.is_some() && video == self.video.as_ref() && burned_subtitles.is_none();
+		let copy_audio = audio.is_some() && audio == source_audio.map(|s| &s.codec).or(self.audio.as_ref());
+		let audio_bitrate = match audio {
+			None => 0,
+			Some(_) if copy_audio => self.audio_streams
+				.get(audio_stream.unwrap_or_else(|| self.default_audio_stream()))
+				.and_then(|s| s.bitrate)
+				.unwrap_or(AUDIO_BITRATE),
+			Some(_) => AUDIO_BITRATE,
+		};
+		let bitrate = match video {
+			None if copy_audio => self.bitrate.or(Some(audio_bitrate)),
+			None => audio.map(|_| AUDIO_BITRATE),
+			Some(_) if copy_video => self.bitrate,
+			Some(_) => Some(video_bitrate(self.bitrate) + audio_bitrate),
+		};
 
+		let container = if video.is_some() {
+			device.container.first()
+		} else {
+			audio.and_then(|a| device.audio_container.iter().find(|c| c.carries(a)))
+		};
+		
 		Format {
-			container: device.container.first().cloned().unwrap_or(ContainerFormat::MKV),
+			container: container.cloned().unwrap_or(ContainerFormat::MKV),
 			video: video.cloned(),
 			audio: audio.cloned(),
+			duration: self.duration,
+			bitrate,
+			resolution: self.resolution,
+			audio_channels: source_audio.map_or(self.audio_channels, |s| s.channels),
+			sample_rate: source_audio.map_or(self.sample_rate, |s| s.sample_rate),
+			tags: self.tags.clone(),
+			audio_streams: self.audio_streams.clone(),
+			audio_stream,
+			subtitles: self.subtitles.clone(),
+			burned_subtitles,
+			start: None,
 		}
 	}
 }
 
+/// The DLNA media format profile for a combination of formats, if there is one.
+pub fn dlna_profile(
+	container: &ContainerFormat,
+	video: Option<&VideoFormat>,
+	audio: Option<&AudioFormat>,
+) -> Option<&'static str> {
+	match (container, video, audio) {
+		(&ContainerFormat::MPEGTS, Some(&VideoFormat::H264), Some(&AudioFormat::AAC)) =>
+			Some("AVC_TS_MP_HD_AAC_MULT5"),
+		(&ContainerFormat::MPEGTS, Some(&VideoFormat::H264), Some(&AudioFormat::MP3)) =>
+			Some("AVC_TS_MP_HD_MPEG1_L3"),
+		(&ContainerFormat::MP4, Some(&VideoFormat::H264), Some(&AudioFormat::AAC)) =>
+			Some("AVC_MP4_MP_SD_AAC_MULT5"),
+		(&ContainerFormat::MP3, None, Some(&AudioFormat::MP3)) => Some("MP3"),
+		_ => None,
+	}
+}
+
+/// `DLNA.ORG_FLAGS` for everything we serve: streaming and background transfer modes,
+/// connection stalling and DLNA 1.5.
+const DLNA_FLAGS: &str = "01700000000000000000000000000000";
+
+fn mime(container: &ContainerFormat, video: Option<&VideoFormat>) -> &'static str {
+	match (container, video) {
+		(&ContainerFormat::MP4, None) => "audio/mp4",
+		(container, _) => container.mime(),
+	}
+}
+
+/// Build an `http-get` protocolInfo string for serving a combination of formats.
+pub fn protocol_info(
+	container: &ContainerFormat,
+	video: Option<&VideoFormat>,
+	audio: Option<&AudioFormat>,
+) -> String {
+	match dlna_profile(container, video, audio) {
+		Some(profile) => format!("http-get:*:{}:DLNA.ORG_PN={}", mime(container, video), profile),
+		None => format!("http-get:*:{}:*", mime(container, video)),
+	}
+}
+
 #[derive(Debug,PartialEq)]
 pub struct Device {
 	pub container: &'static [ContainerFormat],
+	/// Containers for media without video, in order of preference.
+	pub audio_container: &'static [ContainerFormat],
 	pub audio: &'static [AudioFormat],
 	pub video: &'static [VideoFormat],
+	/// Whether the device can display embedded subtitle streams itself.
+	pub soft_subtitles: bool,
 }
 
 #[derive(Deserialize)]
@@ -175,12 +538,33 @@ struct Ffprobe {
 #[derive(Deserialize)]
 struct FfprobeFormat {
 	format_name: String,
+	duration: Option<String>,
+	bit_rate: Option<String>,
+	#[serde(default)]
+	tags: std::collections::HashMap<String, String>,
 }
 
 #[derive(Deserialize)]
 struct FfprobeStream {
 	codec_type: String,
 	codec_name: String,
+	width: Option<u32>,
+	height: Option<u32>,
+	channels: Option<u32>,
+	sample_rate: Option<String>,
+	bit_rate: Option<String>,
+	#[serde(default)]
+	disposition: FfprobeDisposition,
+	#[serde(default)]
+	tags: std::collections::HashMap<String, String>,
+}
+
+#[derive(Default,Deserialize)]
+struct FfprobeDisposition {
+	#[serde(default)]
+	attached_pic: u8,
+	#[serde(default)]
+	default: u8,
 }
 
 pub fn format(input: Input, exec: &crate::Executors) -> crate::Future<Format> {
@@ -194,7 +578,7 @@ pub fn format(input: Input, exec: &crate::Executors) -> crate::Future<Format> {
 	
 	cmd.arg("-of").arg("json");
 	cmd.arg("-show_streams");
-	cmd.arg("-show_entries").arg("format=format_name");
+	cmd.arg("-show_entries").arg("format=format_name,duration,bit_rate:format_tags");
 	
 	// eprintln!("Executing: {:?}", cmd);
 	
@@ -215,14 +599,26 @@ pub fn format(input: Input, exec: &crate::Executors) -> crate::Future<Format> {
 		};
 
 		let Ffprobe{
-			format: FfprobeFormat{format_name},
+			format: FfprobeFormat{format_name, duration, bit_rate, tags},
 			streams,
 		} = out?;
 		
 		let container = match format_name.as_ref() {
+			"flac" => ContainerFormat::FLAC,
 			"matroska" | "matroska,webm" => ContainerFormat::MKV,
-			"mov" | "mov,mp4,m4a,3gp,3g2,mj2" => ContainerFormat::MOV,
+			"mov" | "mov,mp4,m4a,3gp,3g2,mj2" => {
+				// ffprobe doesn't distinguish QuickTime from MP4, the brand does.
+				let brand = tags.iter()
+					.find(|&(k, _)| k.eq_ignore_ascii_case("major_brand"))
+					.map(|(_, v)| v.trim());
+				match brand {
+					Some("qt") | None => ContainerFormat::MOV,
+					Some(_) => ContainerFormat::MP4,
+				}
+			}
+			"mp3" => ContainerFormat::MP3,
 			"mpegts" => ContainerFormat::MPEGTS,
+			"ogg" => ContainerFormat::OGG,
 			"wav" => ContainerFormat::WAV,
 			_ => {
 				eprintln!("Unknown container format: {:?}", format_name);
@@ -234,10 +630,69 @@ pub fn format(input: Input, exec: &crate::Executors) -> crate::Future<Format> {
 			container,
 			audio: None,
 			video: None,
+			duration: duration.and_then(|d| d.parse().ok()),
+			bitrate: bit_rate.and_then(|b| b.parse().ok()),
+			resolution: None,
+			audio_channels: None,
+			sample_rate: None,
+			tags: Tags::default(),
+			audio_streams: Vec::new(),
+			audio_stream: None,
+			subtitles: Vec::new(),
+			burned_subtitles: None,
+			start: None,
 		};
 		
+		// Ogg files keep their tags on the audio stream, use those if the container has none.
+		let mut tags = tags;
+		
 		for stream in streams.into_iter().rev() {
-			let FfprobeStream{codec_type, codec_name, ..} = stream;
+			let FfprobeStream{
+				codec_type, codec_name, width, height, channels, sample_rate, bit_rate,
+				disposition, tags: stream_tags,
+			} = stream;
+			
+			// Cover art is exposed as a video stream.
+			if disposition.attached_pic != 0 { continue }
+			
+			match codec_type.as_ref() {
+				"video" => {
+					if let (Some(width), Some(height)) = (width, height) {
+						format.resolution = Some((width, height));
+					}
+				}
+				"audio" => {
+					let tag = |key| stream_tags.get(key).filter(|v| !v.is_empty()).cloned();
+					format.audio_streams.push(AudioStream {
+						codec: match codec_name.as_ref() {
+							"aac" => AudioFormat::AAC,
+							"flac" => AudioFormat::FLAC,
+							"mp3" => AudioFormat::MP3,
+							"opus" => AudioFormat::Opus,
+							"vorbis" => AudioFormat::Vorbis,
+							codec => AudioFormat::Other(codec.to_string()),
+						},
+						bitrate: bit_rate.and_then(|b| b.parse().ok()),
+						channels,
+						sample_rate: sample_rate.and_then(|r| r.parse().ok()),
+						language: tag("language"),
+						title: tag("title"),
+						default: disposition.default != 0,
+					});
+					if tags.is_empty() {
+						tags = stream_tags;
+					}
+				}
+				"subtitle" => {
+					let tag = |key| stream_tags.get(key).filter(|v| !v.is_empty()).cloned();
+					format.subtitles.push(SubtitleStream {
+						codec: codec_name.clone(),
+						language: tag("language"),
+						title: tag("title"),
+					});
+				}
+				_ => {}
+			}
 			
 			println!("{} {}", codec_type, codec_name);
 			match (codec_type.as_ref(), codec_name.as_ref()) {
@@ -247,20 +702,78 @@ pub fn format(input: Input, exec: &crate::Executors) -> crate::Future<Format> {
 					format.video = Some(VideoFormat::HEVC),
 				("video", codec) =>
 					format.video = Some(VideoFormat::Other(codec.to_string())),
-				("audio", "aac") =>
-					format.audio = Some(AudioFormat::AAC),
-				("audio", codec) =>
-					format.audio = Some(AudioFormat::Other(codec.to_string())),
-				("subtitle", _) => {},
+				("audio", _) | ("subtitle", _) => {},
 				other => eprintln!("Ignoring unknown stream {:?}", other),
 			}
 		}
 		
+		format.tags = Tags::parse(&tags);
+		// Streams were visited in reverse.
+		format.audio_streams.reverse();
+		format.subtitles.reverse();
+		
+		if let Some(stream) = format.audio_streams.get(format.default_audio_stream()) {
+			format.audio = Some(stream.codec.clone());
+			format.audio_channels = stream.channels;
+			format.sample_rate = stream.sample_rate;
+		}
+		
 		eprintln!("{:?}", format);
 		Ok(format)
 	}))
 }
 
+/// Render a single frame of `input` as a JPEG no larger than `max_size`.
+///
+/// `seek` is the position in seconds of the frame.
+pub fn thumbnail(
+	input: Input,
+	seek: Option<f64>,
+	max_size: (u32, u32),
+	out: &std::path::Path,
+	exec: &crate::Executors,
+) -> crate::Result<()> {
+	let mut cmd = start_ffmpeg();
+	cmd.stdout(std::process::Stdio::null());
+	cmd.stderr(std::process::Stdio::null());
+	
+	if let Some(seek) = seek {
+		cmd.arg("-ss").arg(format!("{:.3}", seek));
+	}
+	add_input(input, exec, &mut cmd)?;
+	
+	let (width, height) = max_size;
+	cmd.arg("-frames:v").arg("1");
+	cmd.arg("-vf").arg(format!(
+		"scale=w='min({},iw)':h='min({},ih)':force_original_aspect_ratio=decrease",
+		width, height));
+	cmd.args(&["-f", "image2", "-c:v", "mjpeg", "-y"]).arg(out);
+	
+	let status = cmd.status().chain_err(|| "Error executing ffmpeg")?;
+	if !status.success() {
+		return Err(crate::ErrorKind::Other(format!("ffmpeg exited: {:?}", status)).into())
+	}
+	Ok(())
+}
+
+/// Convert a subtitle file to `format`.
+///
+/// This blocks while ffmpeg runs.
+pub fn convert_subtitles(input: Input, format: SubtitleFormat, exec: &crate::Executors)
+	-> crate::Result<Vec<u8>>
+{
+	let mut cmd = start_ffmpeg();
+	cmd.stderr(std::process::Stdio::null());
+	add_input(input, exec, &mut cmd)?;
+	cmd.args(&["-f", format.ffmpeg_format(), "-"]);
+	
+	let output = cmd.output().chain_err(|| "Error executing ffmpeg")?;
+	if !output.status.success() {
+		return Err(crate::ErrorKind::Other(format!("ffmpeg exited: {:?}", output.status)).into())
+	}
+	Ok(output.stdout)
+}
+
 #[derive(Debug)]
 struct Media {
 	file: std::sync::Arc<MediaFile>
@@ -269,6 +782,8 @@ struct Media {
 #[derive(Debug)]
 struct MediaFile {
 	file: std::fs::File,
+	/// The size reported for the media, the output is padded or truncated to match.
+	estimate: Option<u64>,
 	progress: std::sync::Mutex<MediaProgress>,
 }
 
@@ -276,19 +791,32 @@ struct MediaFile {
 struct MediaProgress {
 	size: u64,
 	complete: bool,
+	/// ffmpeg exited unsuccessfully so the output is incomplete.
+	failed: bool,
 	blocked: Vec<futures::task::Task>,
 }
 
 impl crate::Media for Media {
 	fn size(&self) -> crate::MediaSize {
 		let progress = self.file.progress.lock().unwrap();
-		crate::MediaSize {
-			available: progress.size,
-			total: if progress.complete { Some(progress.size) } else { None },
+		match self.file.estimate {
+			Some(estimate) => crate::MediaSize {
+				available: if progress.complete && !progress.failed {
+					estimate
+				} else {
+					progress.size.min(estimate)
+				},
+				total: Some(estimate),
+			},
+			None => crate::MediaSize {
+				available: progress.size,
+				total: if progress.complete { Some(progress.size) } else { None },
+			},
 		}
 	}
 	
 	fn read_range(&self, start: u64, end: u64) -> crate::ByteStream {
+		let end = self.file.estimate.map_or(end, |estimate| end.min(estimate));
 		Box::new(MediaStream{file: self.file.clone(), offset: start, end: end})
 	}
 }
@@ -328,9 +856,20 @@ impl futures::Stream for MediaStream {
 						progress.blocked.push(futures::task::current());
 						return Ok(futures::Async::NotReady)
 					}
+					if progress.failed {
+						return Err(crate::ErrorKind::Other("Transcode failed.".to_string()).into())
+					}
 					progress.size.min(self.end)
 				};
 				
+				// The transcode came out smaller than estimated, pad it to the promised size.
+				if self.file.estimate.is_some() && size <= self.offset {
+					buf.clear();
+					buf.resize(buf_size, 0);
+					self.offset += buf_size as u64;
+					return Ok(futures::Async::Ready(Some(buf)))
+				}
+				
 				if size > self.offset {
 					unsafe { buf.set_len(buf_size); }
 					let len = self.read(&mut buf)?;
@@ -363,23 +902,90 @@ pub fn transcode(source: &Format, target: &Format, input: Input, exec: &crate::E
 		{ use nix::sys::stat::*; S_IRUSR | S_IWUSR })?;
 	let file = unsafe { std::fs::File::from_raw_fd(fd) };
 	
+	let burn_path = match (target.burned_subtitles, &input) {
+		(Some(_), &Input::Uri(path)) => Some(path.to_owned()),
+		_ => None,
+	};
+	
 	let mut cmd = start_ffmpeg();
 	// cmd.stderr(std::process::Stdio::null());
+	if let Some(start) = target.start {
+		cmd.arg("-ss").arg(format!("{:.3}", start));
+	}
 	add_input(input, exec, &mut cmd)?;
 	
+	let audio_stream = target.audio_stream.unwrap_or_else(|| source.default_audio_stream());
+	let source_audio = match source.audio_streams.get(audio_stream) {
+		Some(stream) => Some(&stream.codec),
+		None if target.audio_stream.is_some() =>
+			return Err(crate::ErrorKind::NotFound(format!("audio stream {}", audio_stream)).into()),
+		None => source.audio.as_ref(),
+	};
+	
+	let mut video_map = "0:v:0".to_string();
+	if let Some(i) = target.burned_subtitles {
+		let stream = source.subtitles.get(i)
+			.ok_or_else(|| crate::ErrorKind::NotFound(format!("subtitle stream {}", i)))?;
+		if stream.is_bitmap() {
+			cmd.arg("-filter_complex").arg(format!("[0:v:0][0:s:{}]overlay[v]", i));
+			video_map = "[v]".to_string();
+		} else {
+			let path = burn_path.ok_or_else(|| crate::ErrorKind::Other(
+				"Text subtitles can only be burned in from local files.".to_string()))?;
+			let subtitles = format!("subtitles=filename={}:si={}",
+				escape_filter_value(&path.to_string_lossy()), i);
+			// Seeking the input resets timestamps but the filter reads the file from the start.
+			let filter = match target.start {
+				Some(start) => format!("setpts=PTS+{:.3}/TB,{},setpts=PTS-STARTPTS", start, subtitles),
+				None => subtitles,
+			};
+			cmd.arg("-vf").arg(filter);
+		}
+	}
+	
+	if target.video.is_some() {
+		cmd.arg("-map").arg(video_map);
+	}
+	if target.audio.is_some() {
+		cmd.arg("-map").arg(format!("0:a:{}?", audio_stream));
+	}
+	// Mapping streams turns off the default selection so keep subtitles that the output can carry.
+	if target.container == ContainerFormat::MKV && target.burned_subtitles.is_none() {
+		if source.container == ContainerFormat::MKV {
+			cmd.args(&["-map", "0:s?", "-c:s", "copy"]);
+		} else {
+			// Other containers' subtitles may not fit in Matroska, convert the text ones.
+			for (i, stream) in source.subtitles.iter().enumerate() {
+				if !stream.is_bitmap() {
+					cmd.arg("-map").arg(format!("0:s:{}", i));
+				}
+			}
+			cmd.args(&["-c:s", "ass"]);
+		}
+	}
+	
 	if let Some(ref f) = target.video {
-		cmd.arg("-c:v").args(if target.video == source.video {
-			&["copy"]
+		if target.video == source.video && target.burned_subtitles.is_none() {
+			cmd.args(&["-c:v", "copy"]);
 		} else {
-			f.ffmpeg_encoder_and_flags()
-		});
+			let bitrate = video_bitrate(source.bitrate);
+			cmd.arg("-c:v").args(f.ffmpeg_encoder_and_flags());
+			cmd.arg("-b:v").arg(bitrate.to_string());
+			cmd.arg("-maxrate").arg(bitrate.to_string());
+			cmd.arg("-bufsize").arg((bitrate * 2).to_string());
+		}
+	}
+	if target.video.is_none() {
+		// Drop cover art and other attached pictures.
+		cmd.arg("-vn");
 	}
 	if let Some(ref f) = target.audio {
-		cmd.arg("-c:a").args(if target.audio == source.audio {
-			&["copy"]
+		if target.audio.as_ref() == source_audio {
+			cmd.args(&["-c:a", "copy"]);
 		} else {
-			f.ffmpeg_id()
-		});
+			cmd.arg("-c:a").args(f.ffmpeg_id());
+			cmd.arg("-b:a").arg(AUDIO_BITRATE.to_string());
+		}
 	}
 	cmd.arg("-f").args(target.container.ffmpeg_encoder_and_flags());
 	
@@ -393,28 +999,45 @@ pub fn transcode(source: &Format, target: &Format, input: Input, exec: &crate::E
 	
 	let mut child = cmd.spawn().chain_err(|| "Error executing ffmpeg")?;
 	
+	let estimate = target.estimated_size();
+	eprintln!("Estimated size: {:?}", estimate);
+	
 	let media_file = std::sync::Arc::new(MediaFile{
 		file: file.try_clone()?,
+		estimate,
 		progress: std::sync::Mutex::new(MediaProgress{
 			size: 0,
 			complete: false,
+			failed: false,
 			blocked: Vec::new(),
 		}),
 	});
 	
-	let media_file_thread = media_file.clone();
+	// Only hold a weak reference so that ffmpeg is stopped once nobody can read the output.
+	let media_file_thread = std::sync::Arc::downgrade(&media_file);
 	std::thread::spawn(move || {
-		loop {
+		let status = loop {
 			std::thread::sleep(std::time::Duration::from_secs(1));
 			
 			match child.try_wait() {
-				Ok(Some(_)) => break,
+				Ok(Some(status)) => break status,
 				Ok(None) => {},
 				Err(e) => eprintln!("Error waiting for ffmpeg: {:?}", e),
 			}
 			
+			let media_file = match media_file_thread.upgrade() {
+				Some(media_file) => media_file,
+				None => {
+					eprintln!("Transcode abandoned, stopping ffmpeg.");
+					if let Err(e) = child.kill().and_then(|_| child.wait()) {
+						eprintln!("Error stopping ffmpeg: {:?}", e);
+					}
+					return
+				}
+			};
+			
 			let metadata = file.metadata();
-			let mut progress = media_file_thread.progress.lock().unwrap();
+			let mut progress = media_file.progress.lock().unwrap();
 			match metadata {
 				Ok(metadata) => progress.size = metadata.len(),
 				Err(e) => eprintln!("Error reading transcoded file size: {:?}", e),
@@ -423,16 +1046,25 @@ pub fn transcode(source: &Format, target: &Format, input: Input, exec: &crate::E
 			for task in progress.blocked.drain(..) {
 				task.notify();
 			}
-		}
+		};
 		
-		eprintln!("Transcoding complete.");
+		if status.success() {
+			eprintln!("Transcoding complete.");
+		} else {
+			eprintln!("Transcoding failed: {:?}", status);
+		}
+		let media_file = match media_file_thread.upgrade() {
+			Some(media_file) => media_file,
+			None => return,
+		};
 		let metadata = file.metadata();
-		let mut progress = media_file_thread.progress.lock().unwrap();
+		let mut progress = media_file.progress.lock().unwrap();
 		match metadata {
 			Ok(metadata) => progress.size = metadata.len(),
 			Err(e) => eprintln!("Error reading transcoded file size: {:?}", e),
 		}
 		progress.complete = true;
+		progress.failed = !status.success();
 		for task in progress.blocked.drain(..) {
 			task.notify();
 		}
@@ -440,3 +1072,92 @@ pub fn transcode(source: &Format, target: &Format, input: Input, exec: &crate::E
 	
 	Ok(std::sync::Arc::new(Media{file: media_file}))
 }
+
+/// Escape a value for use as a filter option inside a filtergraph.
+fn escape_filter_value(value: &str) -> String {
+	let escape = |value: &str, special: &[char]| {
+		let mut r = String::with_capacity(value.len());
+		for c in value.chars() {
+			if special.contains(&c) { r.push('\\') }
+			r.push(c);
+		}
+		r
+	};
+	
+	let option = escape(value, &['\\', '\'', ':']);
+	escape(&option, &['\\', '\'', '[', ']', ',', ';'])
+}
+
+#[test]
+fn test_escape_filter_value() {
+	assert_eq!(escape_filter_value("/media/movie.mkv"), "/media/movie.mkv");
+	assert_eq!(escape_filter_value("/a:b/it's [1].mkv"), r"/a\\:b/it\\\'s \[1\].mkv");
+}
+
+#[test]
+fn test_preferred_audio_stream() {
+	let stream = |language: &str, default| AudioStream {
+		codec: AudioFormat::AAC,
+		bitrate: None,
+		channels: Some(2),
+		sample_rate: None,
+		language: Some(language.to_string()),
+		title: None,
+		default,
+	};
+	let mut format: Format = serde_json::from_str(r#"{"container": "MKV"}"#).unwrap();
+	assert_eq!(format.preferred_audio_stream(&["jpn".to_string()]), 0);
+	
+	format.audio_streams = vec![stream("eng", false), stream("jpn", true), stream("fre", false)];
+	assert_eq!(format.default_audio_stream(), 1);
+	assert_eq!(format.preferred_audio_stream(&[]), 1);
+	assert_eq!(format.preferred_audio_stream(&["ger".to_string(), "FRE".to_string()]), 2);
+}
+
+#[test]
+fn test_protocol_info() {
+	let format: Format = serde_json::from_str(
+		r#"{"container": "MPEGTS", "video": "H264", "audio": "AAC"}"#).unwrap();
+	assert_eq!(format.mime(), "video/mp2t");
+	assert_eq!(format.content_features(true), format!(
+		"DLNA.ORG_PN=AVC_TS_MP_HD_AAC_MULT5;DLNA.ORG_OP=10;DLNA.ORG_CI=1;DLNA.ORG_FLAGS={}",
+		DLNA_FLAGS));
+	assert_eq!(format.protocol_info(true),
+		format!("http-get:*:video/mp2t:{}", format.content_features(true)));
+	
+	let format: Format = serde_json::from_str(r#"{"container": "MP4", "audio": "AAC"}"#).unwrap();
+	assert_eq!(format.mime(), "audio/mp4");
+}
+
+#[test]
+fn test_same_output() {
+	let format: Format = serde_json::from_str(
+		r#"{"container": "MOV", "video": "H264", "audio": "AAC"}"#).unwrap();
+	let mkv = crate::devices::profile("mkv").unwrap();
+	let mpegts = crate::devices::profile("mpegts").unwrap();
+	
+	let target = format.transcode_for(mkv, None, None);
+	assert!(target.same_output(&format.transcode_for(mkv, None, None)));
+	assert!(!target.same_output(&format.transcode_for(mpegts, None, None)));
+	assert!(!target.same_output(&format.transcode_for(mkv, Some(1), None)));
+}
+
+#[test]
+fn test_tags() {
+	let raw = [
+		("ARTIST", "Artist"),
+		("album_artist", "Various"),
+		("Album", " Album "),
+		("GENRE", ""),
+		("track", "3/12"),
+	].iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect();
+	
+	assert_eq!(Tags::parse(&raw), Tags {
+		title: None,
+		artist: Some("Artist".to_string()),
+		album_artist: Some("Various".to_string()),
+		album: Some("Album".to_string()),
+		genre: None,
+		track: Some(3),
+	});
+}
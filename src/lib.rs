@@ -5,6 +5,7 @@ extern crate futures;
 extern crate futures_cpupool;
 #[macro_use] extern crate error_chain;
 #[macro_use] extern crate hyper;
+extern crate inotify;
 #[macro_use] extern crate lazy_static;
 extern crate lru_cache;
 extern crate nix;
@@ -30,7 +31,13 @@ pub mod dlna;
 mod error;
 mod ffmpeg;
 pub mod local;
+pub mod metadata;
+pub mod music;
 pub mod root;
+pub mod scan;
+pub mod thumbnail;
+mod updates;
+pub mod watch;
 mod xml;
 
 pub use crate::error::{Error,ErrorKind,Result};
@@ -61,13 +68,20 @@ impl<T: std::io::Read> futures::Stream for ReadStream<T> {
 	}
 }
 
-#[derive(Debug)]
+#[derive(Clone,Debug)]
 pub struct Executors {
-	handle: tokio_core::reactor::Handle,
+	remote: tokio_core::reactor::Remote,
 	cpupool: std::sync::Arc<futures_cpupool::CpuPool>,
 }
 
 impl Executors {
+	pub fn new(
+		remote: tokio_core::reactor::Remote,
+		cpupool: std::sync::Arc<futures_cpupool::CpuPool>,
+	) -> Self {
+		Executors{remote, cpupool}
+	}
+
 	fn spawn<
 		F: 'static + futures::future::Future<Item=(),Error=Error> + Send>
 		(&self, f: F) -> Result<()>
@@ -80,6 +94,7 @@ impl Executors {
 
 #[derive(PartialEq)]
 pub enum Type {
+	Audio,
 	Directory,
 	Image,
 	Subtitles,
@@ -104,6 +119,7 @@ pub trait Object: Send + Sync + std::fmt::Debug {
 
 	fn dlna_class(&self) -> &'static str {
 		match self.file_type() {
+			Type::Audio => "object.item.audioItem.musicTrack",
 			Type::Directory => "object.container.storageFolder",
 			Type::Image => "object.item.imageItem.photo",
 			Type::Subtitles => "object.item",
@@ -114,7 +130,19 @@ pub trait Object: Send + Sync + std::fmt::Debug {
 
 	fn title(&self) -> String;
 
+	fn modified(&self) -> Option<std::time::SystemTime> { None }
+	fn size(&self) -> Option<u64> { None }
+
+	/// Identifies the current version of the underlying file for caching probe results.
+	fn metadata_key(&self) -> Option<crate::metadata::Key> { None }
+
 	fn is_dir(&self) -> bool;
+
+	/// If this container only regroups objects that are found elsewhere in the tree.
+	///
+	/// Views are skipped when walking the whole tree so that objects aren't found twice.
+	fn is_view(&self) -> bool { false }
+
 	fn lookup(&self, id: &str) -> Result<Box<dyn Object>>;
 
 	fn children(&self) -> Result<Vec<Box<dyn Object>>>;
@@ -154,6 +182,9 @@ pub trait Media: Send + Sync + std::fmt::Debug {
 
 	fn read_range(&self, start: u64, end: u64) -> ByteStream;
 	
+	/// Identifies the content for conditional requests, `None` if it isn't stable.
+	fn version(&self) -> Option<crate::metadata::Key> { None }
+	
 	fn read_all(&self) -> ByteStream {
 		self.read_range(0, u64::max_value())
 	}
@@ -70,14 +70,24 @@ impl crate::Object for Object {
 		if self.is_dir() { return crate::Type::Directory }
 		
 		match self.path.extension().and_then(std::ffi::OsStr::to_str) {
+			Some("ass") => crate::Type::Subtitles,
 			Some("avi") => crate::Type::Video,
+			Some("flac") => crate::Type::Audio,
 			Some("jpeg") => crate::Type::Image,
 			Some("jpg") => crate::Type::Image,
+			Some("m4a") => crate::Type::Audio,
 			Some("m4v") => crate::Type::Video,
 			Some("mkv") => crate::Type::Video,
+			Some("mp3") => crate::Type::Audio,
 			Some("mp4") => crate::Type::Video,
+			Some("oga") => crate::Type::Audio,
+			Some("ogg") => crate::Type::Audio,
+			Some("opus") => crate::Type::Audio,
 			Some("png") => crate::Type::Image,
 			Some("srt") => crate::Type::Subtitles,
+			Some("ssa") => crate::Type::Subtitles,
+			Some("vtt") => crate::Type::Subtitles,
+			Some("wav") => crate::Type::Audio,
 			_ => crate::Type::Other,
 		}
 	}
@@ -88,6 +98,20 @@ impl crate::Object for Object {
 			.unwrap_or_else(|| "<No Title>".to_string())
 	}
 	
+	fn modified(&self) -> Option<std::time::SystemTime> {
+		self.path.metadata().and_then(|m| m.modified()).ok()
+	}
+	
+	fn size(&self) -> Option<u64> {
+		self.path.metadata().ok()
+			.filter(|m| m.is_file())
+			.map(|m| m.len())
+	}
+	
+	fn metadata_key(&self) -> Option<crate::metadata::Key> {
+		crate::metadata::Key::for_path(&self.path).ok()
+	}
+	
 	fn is_dir(&self) -> bool { self.path.is_dir() }
 	
 	fn lookup(&self, id: &str) -> crate::Result<Box<dyn crate::Object>> {
@@ -139,6 +163,10 @@ impl crate::Media for Media {
 		}
 	}
 	
+	fn version(&self) -> Option<crate::metadata::Key> {
+		crate::metadata::Key::for_path(&self.path).ok()
+	}
+	
 	fn read_range(&self, start: u64, end: u64) -> crate::ByteStream {
 		let mut file = match std::fs::File::open(&self.path) {
 			Ok(f) => f,
@@ -151,7 +179,7 @@ impl crate::Media for Media {
 			let e = crate::Error::with_chain(e, format!("Error seeking {:?}", self.path));
 			return Box::new(futures::future::err(e).into_stream())
 		}
-		Box::new(crate::ReadStream(file.take(end)))
+		Box::new(crate::ReadStream(file.take(end.saturating_sub(start))))
 	}
 }
 
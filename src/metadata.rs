@@ -0,0 +1,196 @@
+use futures;
+use futures::Future;
+use serde_json;
+use std;
+use std::io::{BufRead, Write};
+
+use crate::error::ResultExt;
+
+/// Identifies a specific version of a file.
+#[derive(Clone,Debug,Deserialize,Eq,Hash,PartialEq,Serialize)]
+pub struct Key {
+	pub path: std::path::PathBuf,
+	/// Modification time as (seconds, nanoseconds) since the epoch.
+	pub mtime: (u64, u32),
+	pub size: u64,
+}
+
+impl Key {
+	pub fn for_path(path: &std::path::Path) -> crate::Result<Key> {
+		let metadata = path.metadata()
+			.chain_err(|| format!("Error reading metadata of {:?}", path))?;
+		let mtime = metadata.modified()?
+			.duration_since(std::time::UNIX_EPOCH)
+			.unwrap_or_default();
+
+		Ok(Key {
+			path: path.to_owned(),
+			mtime: (mtime.as_secs(), mtime.subsec_nanos()),
+			size: metadata.len(),
+		})
+	}
+}
+
+/// Bumped when probing extracts new information so that old records are ignored.
+const VERSION: u32 = 4;
+
+/// Just the version of a record so that records of other versions are skipped without parsing.
+#[derive(Deserialize)]
+struct RecordVersion {
+	#[serde(default)]
+	version: u32,
+}
+
+#[derive(Debug,Deserialize,Serialize)]
+struct Record {
+	#[serde(default)]
+	version: u32,
+	key: Key,
+	format: crate::ffmpeg::Format,
+}
+
+/// Probe results that persist across restarts.
+///
+/// The store is a log of JSON records, one per line. Newer records replace older ones for the
+/// same path and the log is compacted when opened.
+#[derive(Debug)]
+pub struct Store {
+	inner: std::sync::Mutex<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+	entries: std::collections::HashMap<std::path::PathBuf, Record>,
+	log: Option<std::fs::File>,
+}
+
+impl Store {
+	/// A store that is never written to disk.
+	pub fn in_memory() -> Store {
+		Store {
+			inner: std::sync::Mutex::new(Inner {
+				entries: std::collections::HashMap::new(),
+				log: None,
+			}),
+		}
+	}
+
+	pub fn open<P: AsRef<std::path::Path>>(path: P) -> crate::Result<Store> {
+		let path = path.as_ref();
+		let mut entries = std::collections::HashMap::new();
+
+		match std::fs::File::open(path) {
+			Ok(file) => {
+				for line in std::io::BufReader::new(file).lines() {
+					let line = line.chain_err(|| format!("Error reading {:?}", path))?;
+					match serde_json::from_str::<RecordVersion>(&line) {
+						Ok(ref record) if record.version != VERSION => continue,
+						_ => {}
+					}
+					match serde_json::from_str::<Record>(&line) {
+						Ok(record) => { entries.insert(record.key.path.clone(), record); }
+						Err(e) => eprintln!("Ignoring invalid metadata record {:?}: {}", line, e),
+					}
+				}
+			}
+			Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => {}
+			Err(e) => return Err(e).chain_err(|| format!("Error opening {:?}", path)),
+		}
+
+		eprintln!("Loaded metadata for {} files from {:?}", entries.len(), path);
+
+		let tmp = path.with_extension("tmp");
+		let mut compacted = std::fs::File::create(&tmp)
+			.chain_err(|| format!("Error creating {:?}", tmp))?;
+		for record in entries.values() {
+			compacted.write_all(&serialize_record(record)?)?;
+		}
+		std::fs::rename(&tmp, path)
+			.chain_err(|| format!("Error replacing {:?}", path))?;
+
+		let log = std::fs::OpenOptions::new()
+			.append(true)
+			.open(path)
+			.chain_err(|| format!("Error opening {:?}", path))?;
+
+		Ok(Store {
+			inner: std::sync::Mutex::new(Inner {
+				entries,
+				log: Some(log),
+			}),
+		})
+	}
+
+	pub fn len(&self) -> usize {
+		self.inner.lock().unwrap().entries.len()
+	}
+
+	pub fn get(&self, key: &Key) -> Option<crate::ffmpeg::Format> {
+		self.inner.lock().unwrap().entries.get(&key.path)
+			.filter(|record| record.key == *key)
+			.map(|record| record.format.clone())
+	}
+
+	/// Forget the stored result for a path.
+	///
+	/// Stale records are never returned by `get` so this only frees memory. The log is cleaned up
+	/// on the next compaction.
+	pub fn remove(&self, path: &std::path::Path) {
+		self.inner.lock().unwrap().entries.remove(path);
+	}
+
+	pub fn insert(&self, key: Key, format: crate::ffmpeg::Format) {
+		let record = Record{version: VERSION, key, format};
+		let mut inner = self.inner.lock().unwrap();
+
+		if let Some(ref mut log) = inner.log {
+			if let Err(e) = serialize_record(&record).and_then(|r| Ok(log.write_all(&r)?)) {
+				eprintln!("Error saving metadata for {:?}: {}", record.key.path, e);
+			}
+		}
+
+		inner.entries.insert(record.key.path.clone(), record);
+	}
+}
+
+fn serialize_record(record: &Record) -> crate::Result<Vec<u8>> {
+	let mut buf = serde_json::to_vec(record)?;
+	buf.push(b'\n');
+	Ok(buf)
+}
+
+/// Probe the format of an item, reusing the stored result if the file hasn't changed.
+pub fn format(
+	store: &std::sync::Arc<Store>,
+	item: &dyn crate::Object,
+	exec: &crate::Executors,
+) -> crate::Future<crate::ffmpeg::Format> {
+	let key = item.metadata_key();
+	if let Some(format) = key.as_ref().and_then(|key| store.get(key)) {
+		return Box::new(futures::future::ok(format))
+	}
+
+	let store = store.clone();
+	Box::new(item.format(exec)
+		.inspect(move |format| {
+			if let Some(key) = key {
+				store.insert(key, format.clone());
+			}
+		}))
+}
+
+#[test]
+fn test_stale_records() {
+	let dir = std::env::temp_dir().join(format!("rustymedia-metadata-{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	let path = dir.join("metadata.jsonl");
+	let old = r#"{"key":{"path":"/old.mkv","mtime":[1,0],"size":1},"format":{}}"#;
+	let new = format!(
+		r#"{{"version":{},"key":{{"path":"/new.mkv","mtime":[1,0],"size":1}},"format":{{"container":"MKV"}}}}"#,
+		VERSION);
+	std::fs::write(&path, format!("{}\n{}\n", old, new)).unwrap();
+
+	let store = Store::open(&path).unwrap();
+	assert_eq!(store.len(), 1);
+	std::fs::remove_dir_all(&dir).unwrap();
+}
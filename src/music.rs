@@ -0,0 +1,373 @@
+use std;
+use std::sync::Arc;
+
+/// ID of the music views under `root::Root`.
+pub const ID: &str = "@music";
+
+const UNKNOWN_ALBUM: &str = "Unknown Album";
+const UNKNOWN_ARTIST: &str = "Unknown Artist";
+
+#[derive(Debug)]
+struct Entry {
+	object: Box<dyn crate::Object>,
+	tags: crate::ffmpeg::Tags,
+}
+
+impl Entry {
+	/// The artist albums are grouped by.
+	fn artist(&self) -> &str {
+		self.tags.album_artist.as_ref()
+			.or(self.tags.artist.as_ref())
+			.map(|s| &s[..])
+			.unwrap_or(UNKNOWN_ARTIST)
+	}
+
+	fn album(&self) -> &str {
+		self.tags.album.as_ref().map(|s| &s[..]).unwrap_or(UNKNOWN_ALBUM)
+	}
+
+	fn genre(&self) -> Option<&str> {
+		self.tags.genre.as_ref().map(|s| &s[..])
+	}
+}
+
+/// An index of tagged audio files, filled in by the library scanner.
+#[derive(Debug)]
+pub struct Library {
+	entries: std::sync::RwLock<std::collections::BTreeMap<String, Arc<Entry>>>,
+}
+
+impl Library {
+	pub fn new() -> Self {
+		Library {
+			entries: std::sync::RwLock::new(std::collections::BTreeMap::new()),
+		}
+	}
+
+	pub fn insert(&self, object: Box<dyn crate::Object>, tags: crate::ffmpeg::Tags) {
+		let id = object.id().to_string();
+		self.entries.write().unwrap().insert(id, Arc::new(Entry{object, tags}));
+	}
+
+	/// Remove the track backed by the object `id`, if any.
+	pub fn remove(&self, id: &str) {
+		self.entries.write().unwrap().remove(id);
+	}
+
+	pub fn len(&self) -> usize {
+		self.entries.read().unwrap().len()
+	}
+
+	fn entries(&self) -> Vec<Arc<Entry>> {
+		self.entries.read().unwrap().values().cloned().collect()
+	}
+}
+
+/// Make a tag value usable as a single ID component.
+fn escape(name: &str) -> String {
+	name.replace('/', "\u{2215}")
+}
+
+fn parent_id(id: &str) -> &str {
+	match id.rfind('/') {
+		Some(i) => &id[..i],
+		None => "0",
+	}
+}
+
+fn not_found<T>(id: &str) -> crate::Result<T> {
+	Err(crate::ErrorKind::NotFound(format!("{:?} not found in music library", id)).into())
+}
+
+#[derive(Clone,Debug)]
+enum Kind {
+	Root,
+	Artists,
+	Artist(String),
+	Album(String, String),
+	Genres,
+	Genre(String),
+	Tracks,
+}
+
+impl Kind {
+	/// The ID component of a view of this kind within its parent.
+	fn segment(&self) -> &str {
+		match *self {
+			Kind::Root => ID,
+			Kind::Artists => "artists",
+			Kind::Artist(ref artist) => artist,
+			Kind::Album(_, ref album) => album,
+			Kind::Genres => "genres",
+			Kind::Genre(ref genre) => genre,
+			Kind::Tracks => "tracks",
+		}
+	}
+}
+
+/// A synthetic container that groups the tracks of a `Library`.
+///
+/// The tree is `Artists/<artist>/<album>/<track>`, `Genres/<genre>/<track>` and
+/// `Tracks/<track>`. Tag values are escaped to form IDs and tracks are identified by the ID of
+/// the object they wrap.
+#[derive(Clone,Debug)]
+pub struct View {
+	library: Arc<Library>,
+	id: String,
+	title: String,
+	kind: Kind,
+}
+
+impl View {
+	pub fn root(library: Arc<Library>) -> View {
+		View {
+			library,
+			id: ID.to_string(),
+			title: "Music Library".to_string(),
+			kind: Kind::Root,
+		}
+	}
+
+	fn child(&self, segment: &str, title: String, kind: Kind) -> View {
+		View {
+			library: self.library.clone(),
+			id: format!("{}/{}", self.id, segment),
+			title,
+			kind,
+		}
+	}
+
+	fn track(&self, entry: Arc<Entry>) -> Box<dyn crate::Object> {
+		Box::new(Track {
+			id: format!("{}/{}", self.id, escape(entry.object.id())),
+			entry,
+		})
+	}
+
+	/// Tracks directly in this container.
+	fn tracks(&self) -> Vec<Arc<Entry>> {
+		self.library.entries().into_iter()
+			.filter(|e| match self.kind {
+				Kind::Album(ref artist, ref album) =>
+					escape(e.artist()) == *artist && escape(e.album()) == *album,
+				Kind::Genre(ref genre) => e.genre().map(escape).as_ref() == Some(genre),
+				Kind::Tracks => true,
+				_ => false,
+			})
+			.collect()
+	}
+
+	/// Distinct values of `key` among tracks matching `filter`.
+	fn groups<F, K>(&self, filter: F, key: K) -> Vec<String>
+		where F: Fn(&Entry) -> bool, K: Fn(&Entry) -> Option<&str>
+	{
+		let mut groups = self.library.entries().iter()
+			.filter(|e| filter(e))
+			.filter_map(|e| key(e).map(|k| k.to_string()))
+			.collect::<Vec<_>>();
+		groups.sort_by(|l, r| crate::human_order(&escape(l), &escape(r)));
+		groups.dedup();
+		groups
+	}
+
+	/// The subgroups of this view, as (title, kind) pairs.
+	fn subgroups(&self) -> Vec<(String, Kind)> {
+		match self.kind {
+			Kind::Root => vec![
+				("Artists".to_string(), Kind::Artists),
+				("Genres".to_string(), Kind::Genres),
+				("All Tracks".to_string(), Kind::Tracks),
+			],
+			Kind::Artists => self.groups(|_| true, |e| Some(e.artist())).into_iter()
+				.map(|a| {
+					let kind = Kind::Artist(escape(&a));
+					(a, kind)
+				})
+				.collect(),
+			Kind::Artist(ref artist) => self
+				.groups(|e| escape(e.artist()) == *artist, |e| Some(e.album())).into_iter()
+				.map(|a| {
+					let kind = Kind::Album(artist.clone(), escape(&a));
+					(a, kind)
+				})
+				.collect(),
+			Kind::Genres => self.groups(|_| true, |e| e.genre()).into_iter()
+				.map(|g| {
+					let kind = Kind::Genre(escape(&g));
+					(g, kind)
+				})
+				.collect(),
+			Kind::Album(..) | Kind::Genre(_) | Kind::Tracks => Vec::new(),
+		}
+	}
+
+	/// Resolve `path`, a list of ID components relative to this view.
+	fn resolve(&self, path: &[&str]) -> crate::Result<Box<dyn crate::Object>> {
+		let (first, rest) = match path.split_first() {
+			Some((first, rest)) => (*first, rest),
+			None => return Ok(Box::new(self.clone())),
+		};
+
+		match self.kind {
+			Kind::Album(..) | Kind::Genre(_) | Kind::Tracks => {
+				if !rest.is_empty() { return not_found(rest[0]) }
+				match self.tracks().into_iter().find(|e| escape(e.object.id()) == first) {
+					Some(entry) => Ok(self.track(entry)),
+					None => not_found(first),
+				}
+			}
+			_ => {
+				match self.subgroups().into_iter().find(|&(_, ref kind)| kind.segment() == first) {
+					Some((title, kind)) => self.child(first, title, kind).resolve(rest),
+					None => not_found(first),
+				}
+			}
+		}
+	}
+}
+
+impl crate::Object for View {
+	fn id(&self) -> &str { &self.id }
+	fn parent_id(&self) -> &str { parent_id(&self.id) }
+	fn file_type(&self) -> crate::Type { crate::Type::Directory }
+
+	fn dlna_class(&self) -> &'static str {
+		match self.kind {
+			Kind::Artist(_) => "object.container.person.musicArtist",
+			Kind::Album(..) => "object.container.album.musicAlbum",
+			Kind::Genre(_) => "object.container.genre.musicGenre",
+			_ => "object.container",
+		}
+	}
+
+	fn title(&self) -> String { self.title.clone() }
+	fn is_dir(&self) -> bool { true }
+	fn is_view(&self) -> bool { true }
+
+	fn lookup(&self, id: &str) -> crate::Result<Box<dyn crate::Object>> {
+		let path = id.split('/')
+			.filter(|s| !s.is_empty())
+			.collect::<Vec<_>>();
+		self.resolve(&path)
+	}
+
+	fn children(&self) -> crate::Result<Vec<Box<dyn crate::Object>>> {
+		let tracks = self.tracks().into_iter().map(|e| self.track(e));
+		let groups = self.subgroups().into_iter()
+			.map(|(title, kind)| -> Box<dyn crate::Object> {
+				Box::new(self.child(kind.segment(), title, kind.clone()))
+			});
+		Ok(groups.chain(tracks).collect())
+	}
+}
+
+/// A track listed in a `View`, delegating to the object it was found as.
+#[derive(Debug)]
+struct Track {
+	id: String,
+	entry: Arc<Entry>,
+}
+
+impl crate::Object for Track {
+	fn id(&self) -> &str { &self.id }
+	fn parent_id(&self) -> &str { parent_id(&self.id) }
+	fn file_type(&self) -> crate::Type { self.entry.object.file_type() }
+
+	fn title(&self) -> String {
+		self.entry.tags.title.clone().unwrap_or_else(|| self.entry.object.title())
+	}
+
+	fn modified(&self) -> Option<std::time::SystemTime> { self.entry.object.modified() }
+	fn size(&self) -> Option<u64> { self.entry.object.size() }
+	fn metadata_key(&self) -> Option<crate::metadata::Key> { self.entry.object.metadata_key() }
+
+	fn is_dir(&self) -> bool { false }
+
+	fn lookup(&self, id: &str) -> crate::Result<Box<dyn crate::Object>> {
+		not_found(id)
+	}
+
+	fn children(&self) -> crate::Result<Vec<Box<dyn crate::Object>>> {
+		Ok(Vec::new())
+	}
+
+	fn ffmpeg_input(&self, exec: &crate::Executors) -> crate::Result<crate::ffmpeg::Input> {
+		self.entry.object.ffmpeg_input(exec)
+	}
+
+	fn format(&self, exec: &crate::Executors) -> crate::Future<crate::ffmpeg::Format> {
+		self.entry.object.format(exec)
+	}
+
+	fn body(&self, exec: &crate::Executors) -> crate::Result<std::sync::Arc<dyn crate::Media>> {
+		self.entry.object.body(exec)
+	}
+
+	fn transcoded_body(
+		&self, exec: &crate::Executors,
+		source: &crate::ffmpeg::Format,
+		target: &crate::ffmpeg::Format
+	) -> crate::Result<std::sync::Arc<dyn crate::Media>> {
+		self.entry.object.transcoded_body(exec, source, target)
+	}
+}
+
+#[cfg(test)]
+#[derive(Debug)]
+struct Stub(&'static str);
+
+#[cfg(test)]
+impl crate::Object for Stub {
+	fn id(&self) -> &str { self.0 }
+	fn parent_id(&self) -> &str { parent_id(self.0) }
+	fn file_type(&self) -> crate::Type { crate::Type::Audio }
+	fn title(&self) -> String { self.0.to_string() }
+	fn is_dir(&self) -> bool { false }
+	fn lookup(&self, id: &str) -> crate::Result<Box<dyn crate::Object>> { not_found(id) }
+	fn children(&self) -> crate::Result<Vec<Box<dyn crate::Object>>> { Ok(Vec::new()) }
+}
+
+#[test]
+fn test_views() {
+	let library = Arc::new(Library::new());
+	let tags = |artist: &str, album: &str, genre: Option<&str>| crate::ffmpeg::Tags {
+		artist: Some(artist.to_string()),
+		album: Some(album.to_string()),
+		genre: genre.map(|g| g.to_string()),
+		..Default::default()
+	};
+	library.insert(Box::new(Stub("M/a/1.mp3")), tags("AC/DC", "Back in Black", Some("Rock")));
+	library.insert(Box::new(Stub("M/a/2.mp3")), tags("AC/DC", "Back in Black", Some("Rock")));
+	library.insert(Box::new(Stub("M/b.flac")), tags("Bach", "Suites", None));
+
+	let root = View::root(library.clone());
+	let ids = |object: &dyn crate::Object| object.children().unwrap().iter()
+		.map(|c| c.id().to_string())
+		.collect::<Vec<_>>();
+	use crate::Object;
+
+	assert_eq!(ids(&root), ["@music/artists", "@music/genres", "@music/tracks"]);
+
+	let artists = root.lookup("artists").unwrap();
+	assert_eq!(ids(&*artists), ["@music/artists/AC\u{2215}DC", "@music/artists/Bach"]);
+
+	let album = root.lookup("artists/AC\u{2215}DC/Back in Black").unwrap();
+	assert_eq!(album.title(), "Back in Black");
+	assert_eq!(album.parent_id(), "@music/artists/AC\u{2215}DC");
+	assert_eq!(album.dlna_class(), "object.container.album.musicAlbum");
+	assert_eq!(ids(&*album), [
+		"@music/artists/AC\u{2215}DC/Back in Black/M\u{2215}a\u{2215}1.mp3",
+		"@music/artists/AC\u{2215}DC/Back in Black/M\u{2215}a\u{2215}2.mp3",
+	]);
+
+	let track = root.lookup("genres/Rock/M\u{2215}a\u{2215}2.mp3").unwrap();
+	assert_eq!(track.parent_id(), "@music/genres/Rock");
+	assert_eq!(track.title(), "M/a/2.mp3");
+	assert_eq!(ids(&*root.lookup("tracks").unwrap()).len(), 3);
+
+	assert!(root.lookup("artists/Nobody").is_err());
+	assert!(root.lookup("genres/Rock/M\u{2215}b.flac").is_err());
+
+	library.remove("M/b.flac");
+	assert_eq!(ids(&*artists), ["@music/artists/AC\u{2215}DC"]);
+}
@@ -0,0 +1,151 @@
+use error_chain::ChainedError;
+use futures;
+use futures::{Future, Stream};
+use std;
+use std::sync::Arc;
+
+use crate::Object;
+
+/// How many files to probe at once.
+const CONCURRENCY: usize = 2;
+
+/// How often to log progress, in files probed.
+const PROGRESS_INTERVAL: usize = 100;
+
+/// Walk the library in the background, probing new and changed files so that they are ready
+/// before they are first requested. Audio files are added to `library` as they are probed.
+pub fn spawn(
+	root: Arc<crate::root::Root>,
+	metadata: Arc<crate::metadata::Store>,
+	library: Arc<crate::music::Library>,
+	exec: crate::Executors,
+) -> crate::Result<()> {
+	std::thread::Builder::new()
+		.name("scanner".to_string())
+		.spawn(move || scan(&root, &metadata, &library, &exec))?;
+	Ok(())
+}
+
+/// Update `library` for a file reported by `watch::spawn`.
+pub fn changed(
+	root: &Arc<crate::root::Root>,
+	metadata: &Arc<crate::metadata::Store>,
+	library: &Arc<crate::music::Library>,
+	exec: &crate::Executors,
+	change: &crate::watch::Change,
+) {
+	library.remove(&change.object);
+	
+	match root.lookup(&change.object) {
+		Ok(ref item) if item.file_type() == crate::Type::Audio && change.path.is_file() => {}
+		_ => return,
+	}
+	
+	let root = root.clone();
+	let metadata = metadata.clone();
+	let library = library.clone();
+	let probe_exec = exec.clone();
+	let id = change.object.clone();
+	let r = exec.spawn(futures::future::lazy(move || {
+		let item = root.lookup(&id)?;
+		probe(&metadata, &library, item, &probe_exec).wait()
+	}));
+	if let Err(e) = r {
+		eprintln!("Error queuing probe of {:?}: {}", change.object, e.display_chain());
+	}
+}
+
+/// Probe an item, adding it to `library` if it is audio.
+fn probe(
+	metadata: &Arc<crate::metadata::Store>,
+	library: &Arc<crate::music::Library>,
+	item: Box<dyn Object>,
+	exec: &crate::Executors,
+) -> crate::Future<()> {
+	let library = library.clone();
+	Box::new(crate::metadata::format(metadata, &*item, exec)
+		.then(move |r| {
+			match r {
+				Ok(format) => if item.file_type() == crate::Type::Audio {
+					library.insert(item, format.tags);
+				},
+				Err(e) => eprintln!("Error probing {:?}: {}", item.id(), e.display_chain()),
+			}
+			Ok(())
+		}))
+}
+
+fn scan(
+	root: &Arc<crate::root::Root>,
+	metadata: &Arc<crate::metadata::Store>,
+	library: &Arc<crate::music::Library>,
+	exec: &crate::Executors,
+) {
+	let start = std::time::Instant::now();
+	
+	let mut pending = Vec::new();
+	let mut total = 0;
+	find_media(root, metadata, &mut pending, &mut total);
+	eprintln!("Library scan found {} media files, {} need probing or indexing.",
+		total, pending.len());
+	
+	let count = pending.len();
+	let r = futures::stream::iter_ok(pending)
+		.map(|item: Box<dyn Object>| exec.cpupool.spawn(probe(metadata, library, item, exec)))
+		.buffer_unordered(CONCURRENCY)
+		.fold(0, |done, ()| {
+			let done = done + 1;
+			if done % PROGRESS_INTERVAL == 0 {
+				eprintln!("Library scan processed {}/{} files.", done, count);
+			}
+			Ok::<_, crate::Error>(done)
+		})
+		.wait();
+	
+	match r {
+		Ok(done) => eprintln!("Library scan processed {} files in {:?}, {} music tracks.",
+			done, start.elapsed(), library.len()),
+		Err(e) => eprintln!("Library scan failed: {}", e.display_chain()),
+	}
+}
+
+/// Recursively collect the media files under `object` that need to be probed.
+///
+/// Audio files are always included so that they can be indexed, their probe results are
+/// usually already stored.
+fn find_media(
+	object: &dyn Object,
+	metadata: &crate::metadata::Store,
+	pending: &mut Vec<Box<dyn Object>>,
+	total: &mut usize,
+) {
+	let children = match object.children() {
+		Ok(children) => children,
+		Err(e) => {
+			eprintln!("Error scanning {:?}: {}", object.id(), e.display_chain());
+			return
+		}
+	};
+	
+	for entry in children {
+		match entry.file_type() {
+			crate::Type::Directory => if !entry.is_view() {
+				find_media(&*entry, metadata, pending, total)
+			},
+			crate::Type::Audio => {
+				*total += 1;
+				pending.push(entry);
+			}
+			crate::Type::Video => {
+				*total += 1;
+				let probed = entry.metadata_key()
+					.map(|key| metadata.get(&key).is_some())
+					.unwrap_or(true);
+				if !probed {
+					pending.push(entry);
+				}
+			}
+			_ => continue,
+		}
+	}
+}
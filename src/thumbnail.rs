@@ -0,0 +1,110 @@
+use std;
+
+use crate::error::ResultExt;
+
+/// DLNA JPEG thumbnail profiles.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Profile {
+	JpegTn,
+	JpegSm,
+}
+
+impl Profile {
+	pub const ALL: &'static [Profile] = &[Profile::JpegTn, Profile::JpegSm];
+	
+	pub fn name(self) -> &'static str {
+		match self {
+			Profile::JpegTn => "JPEG_TN",
+			Profile::JpegSm => "JPEG_SM",
+		}
+	}
+	
+	pub fn parse(name: &str) -> Option<Profile> {
+		Profile::ALL.iter().cloned().find(|p| p.name() == name)
+	}
+	
+	/// The maximum (width, height) allowed by the profile.
+	fn max_size(self) -> (u32, u32) {
+		match self {
+			Profile::JpegTn => (160, 160),
+			Profile::JpegSm => (640, 480),
+		}
+	}
+	
+	pub fn protocol_info(self) -> String {
+		format!("http-get:*:image/jpeg:DLNA.ORG_PN={}", self.name())
+	}
+}
+
+/// Generated thumbnails, stored on disk by source file version.
+#[derive(Debug)]
+pub struct Cache {
+	dir: std::path::PathBuf,
+	next_tmp: std::sync::atomic::AtomicUsize,
+}
+
+impl Cache {
+	pub fn new<P: Into<std::path::PathBuf>>(dir: P) -> Cache {
+		Cache {
+			dir: dir.into(),
+			next_tmp: std::sync::atomic::AtomicUsize::new(0),
+		}
+	}
+	
+	fn path(&self, key: &crate::metadata::Key, profile: Profile) -> std::path::PathBuf {
+		use std::hash::{Hash, Hasher};
+		
+		let mut hasher = std::collections::hash_map::DefaultHasher::new();
+		key.hash(&mut hasher);
+		self.dir.join(profile.name()).join(format!("{:016x}.jpg", hasher.finish()))
+	}
+	
+	/// Get a thumbnail of `item`, generating it if required.
+	///
+	/// `seek` is the position in seconds to take the frame from. This blocks while ffmpeg runs so
+	/// it should be called from the CPU pool.
+	pub fn get(&self,
+		item: &dyn crate::Object,
+		profile: Profile,
+		seek: Option<f64>,
+		exec: &crate::Executors,
+	) -> crate::Result<Vec<u8>> {
+		let key = item.metadata_key()
+			.ok_or_else(|| crate::ErrorKind::NotAFile(item.id().to_string()))?;
+		let path = self.path(&key, profile);
+		
+		match std::fs::read(&path) {
+			Ok(data) => return Ok(data),
+			Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => {}
+			Err(e) => return Err(e).chain_err(|| format!("Error reading {:?}", path)),
+		}
+		
+		let dir = path.parent().unwrap();
+		std::fs::create_dir_all(dir)
+			.chain_err(|| format!("Error creating {:?}", dir))?;
+		
+		let n = self.next_tmp.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+		let tmp = path.with_extension(format!("{}.tmp.jpg", n));
+		let r = crate::ffmpeg::thumbnail(
+			item.ffmpeg_input(exec)?, seek, profile.max_size(), &tmp, exec)
+			.and_then(|()| {
+				std::fs::rename(&tmp, &path)
+					.chain_err(|| format!("Error saving thumbnail {:?}", path))
+			});
+		if let Err(e) = r {
+			let _ = std::fs::remove_file(&tmp);
+			return Err(e).chain_err(|| format!("Error generating thumbnail of {:?}", item.id()))
+		}
+		
+		eprintln!("Generated {} thumbnail of {:?}", profile.name(), item.id());
+		std::fs::read(&path).chain_err(|| format!("Error reading {:?}", path))
+	}
+}
+
+#[test]
+fn test_profile() {
+	for &profile in Profile::ALL {
+		assert_eq!(Profile::parse(profile.name()), Some(profile));
+	}
+	assert_eq!(Profile::parse("JPEG_LRG"), None);
+}
@@ -0,0 +1,130 @@
+use std;
+
+/// Tracks the ContentDirectory `SystemUpdateID` and per-container update IDs.
+///
+/// Containers are fingerprinted whenever they are listed. If the fingerprint differs from the
+/// last one seen the `SystemUpdateID` is bumped and the container takes on the new value.
+/// Containers can also be marked as changed directly, for example by the filesystem watcher.
+#[derive(Debug)]
+pub struct UpdateIds {
+	system: u32,
+	containers: std::collections::HashMap<String, Container>,
+	/// Containers that changed since the last call to `take_changes`.
+	pending: Vec<String>,
+}
+
+#[derive(Debug)]
+struct Container {
+	/// `None` if the container was marked changed since it was last listed.
+	fingerprint: Option<u64>,
+	update_id: u32,
+}
+
+impl UpdateIds {
+	pub fn new() -> Self {
+		UpdateIds {
+			system: 1,
+			containers: std::collections::HashMap::new(),
+			pending: Vec::new(),
+		}
+	}
+
+	pub fn system(&self) -> u32 { self.system }
+
+	/// The update ID of a container, or the `SystemUpdateID` if it has never been observed.
+	pub fn container(&self, id: &str) -> u32 {
+		self.containers.get(id)
+			.map(|c| c.update_id)
+			.unwrap_or(self.system)
+	}
+
+	/// Record the current fingerprint of a container and return its update ID.
+	pub fn observe(&mut self, id: &str, fingerprint: u64) -> u32 {
+		let system = &mut self.system;
+		let container = self.containers.entry(id.to_string())
+			.or_insert_with(|| Container {
+				fingerprint: Some(fingerprint),
+				update_id: *system,
+			});
+
+		match container.fingerprint {
+			Some(previous) if previous != fingerprint => {
+				*system = system.wrapping_add(1);
+				eprintln!("Container {:?} changed, SystemUpdateID is now {}", id, system);
+				container.update_id = *system;
+				if !self.pending.iter().any(|c| c == id) {
+					self.pending.push(id.to_string());
+				}
+			}
+			_ => {}
+		}
+		container.fingerprint = Some(fingerprint);
+
+		container.update_id
+	}
+
+	/// Mark a container as changed, bumping its update ID and the `SystemUpdateID`.
+	pub fn changed(&mut self, id: &str) -> u32 {
+		self.system = self.system.wrapping_add(1);
+		eprintln!("Container {:?} changed, SystemUpdateID is now {}", id, self.system);
+		self.containers.insert(id.to_string(), Container {
+			fingerprint: None,
+			update_id: self.system,
+		});
+		if !self.pending.iter().any(|c| c == id) {
+			self.pending.push(id.to_string());
+		}
+		self.system
+	}
+
+	/// Containers that changed since the last call along with their current update IDs.
+	pub fn take_changes(&mut self) -> Vec<(String, u32)> {
+		let changed = std::mem::replace(&mut self.pending, Vec::new());
+		changed.into_iter()
+			.map(|id| {
+				let update_id = self.container(&id);
+				(id, update_id)
+			})
+			.collect()
+	}
+}
+
+/// Fingerprint a listing so that added, removed and modified children can be detected.
+///
+/// The result doesn't depend on the order of `children`.
+pub fn fingerprint(children: &[Box<dyn crate::Object>]) -> u64 {
+	use std::hash::{Hash, Hasher};
+
+	children.iter()
+		.map(|child| {
+			let mut hasher = std::collections::hash_map::DefaultHasher::new();
+			child.id().hash(&mut hasher);
+			child.modified().hash(&mut hasher);
+			hasher.finish()
+		})
+		.fold(0, u64::wrapping_add)
+}
+
+#[test]
+fn test_update_ids() {
+	let mut ids = UpdateIds::new();
+	assert_eq!(ids.system(), 1);
+	assert_eq!(ids.observe("a", 10), 1);
+	assert_eq!(ids.observe("b", 20), 1);
+	assert_eq!(ids.observe("a", 10), 1);
+	assert_eq!(ids.system(), 1);
+
+	assert_eq!(ids.observe("a", 11), 2);
+	assert_eq!(ids.system(), 2);
+	assert_eq!(ids.container("a"), 2);
+	assert_eq!(ids.container("b"), 1);
+	assert_eq!(ids.container("c"), 2);
+
+	assert_eq!(ids.changed("b"), 3);
+	assert_eq!(ids.container("b"), 3);
+	assert_eq!(ids.observe("b", 21), 3);
+	assert_eq!(ids.observe("b", 22), 4);
+
+	assert_eq!(ids.take_changes(), vec![("a".to_string(), 2), ("b".to_string(), 4)]);
+	assert_eq!(ids.take_changes(), vec![]);
+}
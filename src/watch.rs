@@ -0,0 +1,122 @@
+use error_chain::ChainedError;
+use inotify;
+use std;
+
+use crate::error::ResultExt;
+
+/// Events that indicate a directory listing or file contents changed.
+///
+/// `CLOSE_WRITE` is used instead of `MODIFY` so that a file being copied in is reported once.
+fn watch_mask() -> inotify::WatchMask {
+	inotify::WatchMask::CLOSE_WRITE
+		| inotify::WatchMask::CREATE
+		| inotify::WatchMask::DELETE
+		| inotify::WatchMask::MOVED_FROM
+		| inotify::WatchMask::MOVED_TO
+}
+
+/// A change to a single entry of a watched directory.
+#[derive(Debug)]
+pub struct Change {
+	/// The ID of the directory containing the entry.
+	pub container: String,
+	/// The ID of the entry that changed.
+	pub object: String,
+	pub path: std::path::PathBuf,
+}
+
+struct Watcher {
+	inotify: inotify::Inotify,
+	dirs: std::collections::HashMap<inotify::WatchDescriptor, (String, std::path::PathBuf)>,
+}
+
+impl Watcher {
+	fn add(&mut self, id: String, path: std::path::PathBuf) -> crate::Result<()> {
+		let wd = self.inotify.add_watch(&path, watch_mask())
+			.chain_err(|| format!("Error watching {:?}", path))?;
+
+		for entry in path.read_dir().chain_err(|| format!("Error listing {:?}", path))? {
+			let entry = entry?;
+			if !entry.file_type()?.is_dir() { continue }
+
+			let child_id = format!("{}/{}", id, entry.file_name().to_string_lossy());
+			if let Err(e) = self.add(child_id, entry.path()) {
+				eprintln!("{}", e);
+			}
+		}
+
+		self.dirs.insert(wd, (id, path));
+		Ok(())
+	}
+
+	fn run<F: Fn(&Change)>(mut self, on_change: F) -> crate::Result<()> {
+		let mut buffer = [0; 4096];
+		loop {
+			let mut changes = Vec::new();
+			for event in self.inotify.read_events_blocking(&mut buffer)? {
+				if event.mask.contains(inotify::EventMask::IGNORED) {
+					self.dirs.remove(&event.wd);
+					continue
+				}
+
+				let (id, path) = match self.dirs.get(&event.wd) {
+					Some(&(ref id, ref path)) => (id, path),
+					None => continue,
+				};
+				let name = match event.name {
+					Some(name) => name,
+					None => continue,
+				};
+
+				changes.push((
+					event.mask.contains(inotify::EventMask::ISDIR)
+						&& event.mask.intersects(
+							inotify::EventMask::CREATE | inotify::EventMask::MOVED_TO),
+					Change {
+						container: id.clone(),
+						object: format!("{}/{}", id, name.to_string_lossy()),
+						path: path.join(name),
+					}));
+			}
+
+			for (new_dir, change) in changes {
+				if new_dir {
+					if let Err(e) = self.add(change.object.clone(), change.path.clone()) {
+						eprintln!("{}", e);
+					}
+				}
+				on_change(&change);
+			}
+		}
+	}
+}
+
+/// Watch local roots for changes.
+///
+/// `roots` are `(id, path)` pairs as passed to `local::Object::new_root`. Subdirectories are
+/// watched recursively, including ones created later. `on_change` is called on a dedicated
+/// thread.
+pub fn spawn<F: Fn(&Change) + Send + 'static>(
+	roots: Vec<(String, std::path::PathBuf)>,
+	on_change: F,
+) -> crate::Result<()> {
+	let mut watcher = Watcher {
+		inotify: inotify::Inotify::init().chain_err(|| "Error initializing inotify")?,
+		dirs: std::collections::HashMap::new(),
+	};
+
+	for (id, path) in roots {
+		watcher.add(id, path)?;
+	}
+	eprintln!("Watching {} directories for changes.", watcher.dirs.len());
+
+	std::thread::Builder::new()
+		.name("watcher".to_string())
+		.spawn(move || {
+			if let Err(e) = watcher.run(on_change) {
+				eprintln!("Watcher stopped: {}", e.display_chain());
+			}
+		})?;
+
+	Ok(())
+}